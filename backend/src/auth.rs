@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use worker::{Env, Request};
+
+use crate::error::{AppError, AppResult};
+
+pub const DEFAULT_MAX_IMAGE_BYTES: usize = 10 * 1024 * 1024; // 10MB
+
+/// API key record stored in the `API_KEYS_KV` namespace under
+/// `api_key:<sha256 hex of the key>`, so raw keys never sit in storage.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyRecord {
+    pub name: String,
+    #[serde(default = "default_tier")]
+    pub tier: String,
+    pub daily_quota: u32,
+    /// Emojis this key may request; any emoji is allowed when absent.
+    #[serde(default)]
+    pub allowed_emojis: Option<Vec<String>>,
+    #[serde(default)]
+    pub max_image_bytes: Option<usize>,
+    #[serde(default)]
+    pub disabled: bool,
+}
+
+/// An authenticated caller.
+#[derive(Debug, Clone)]
+pub struct ApiKey {
+    /// Short, non-secret identifier derived from the key hash, used for rate limit buckets.
+    pub id: String,
+    pub record: ApiKeyRecord,
+}
+
+fn default_tier() -> String {
+    "standard".to_string()
+}
+
+impl ApiKey {
+    pub fn max_image_bytes(&self) -> usize {
+        self.record.max_image_bytes.unwrap_or(DEFAULT_MAX_IMAGE_BYTES)
+    }
+
+    pub fn check_emoji(&self, emoji: &str) -> AppResult<()> {
+        match &self.record.allowed_emojis {
+            Some(allowed) if !allowed.iter().any(|e| e == emoji) => Err(AppError::Forbidden(format!(
+                "The emoji {} is not enabled for this API key",
+                emoji
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// A description can ask for any expression, so keys limited to certain
+    /// emojis can't send one.
+    pub fn check_description(&self) -> AppResult<()> {
+        match self.record.allowed_emojis {
+            Some(_) => Err(AppError::Forbidden(
+                "This API key is limited to certain emojis and cannot send a description".to_string(),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Resolves the optional `Authorization: Bearer` API key on a request.
+///
+/// Requests without the header are anonymous and return `None`; a header that
+/// doesn't resolve to an active key is rejected rather than treated as anonymous.
+pub async fn authenticate(req: &Request, env: &Env) -> AppResult<Option<ApiKey>> {
+    let Some(header) = req.headers().get("Authorization")? else {
+        return Ok(None);
+    };
+
+    let token = parse_bearer(&header)?;
+
+    let kv = env
+        .kv("API_KEYS_KV")
+        .map_err(|_| AppError::Unauthorized("API keys are not enabled on this server".to_string()))?;
+
+    let hash = hash_key(token);
+    let record: ApiKeyRecord = kv
+        .get(&format!("api_key:{}", hash))
+        .json()
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("Invalid API key".to_string()))?;
+
+    if record.disabled {
+        return Err(AppError::Unauthorized("This API key has been disabled".to_string()));
+    }
+
+    Ok(Some(ApiKey {
+        id: hash[..16].to_string(),
+        record,
+    }))
+}
+
+fn parse_bearer(header: &str) -> AppResult<&str> {
+    let (scheme, token) = header
+        .trim()
+        .split_once(' ')
+        .ok_or_else(|| AppError::Unauthorized("Malformed Authorization header".to_string()))?;
+
+    if !scheme.eq_ignore_ascii_case("Bearer") || token.trim().is_empty() {
+        return Err(AppError::Unauthorized(
+            "Authorization header must use the Bearer scheme".to_string(),
+        ));
+    }
+
+    Ok(token.trim())
+}
+
+fn hash_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bearer() {
+        assert_eq!(parse_bearer("Bearer emb_live_123").unwrap(), "emb_live_123");
+        assert_eq!(parse_bearer("bearer  emb_live_123 ").unwrap(), "emb_live_123");
+        assert!(matches!(parse_bearer("Basic dXNlcjpwYXNz"), Err(AppError::Unauthorized(_))));
+        assert!(matches!(parse_bearer("emb_live_123"), Err(AppError::Unauthorized(_))));
+    }
+
+    #[test]
+    fn test_hash_key() {
+        assert_eq!(
+            hash_key("test"),
+            "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+        );
+    }
+
+    #[test]
+    fn test_record_defaults() {
+        let record: ApiKeyRecord =
+            serde_json::from_str(r#"{"name": "acme", "daily_quota": 100}"#).unwrap();
+        assert_eq!(record.tier, "standard");
+        assert!(record.allowed_emojis.is_none());
+        assert!(!record.disabled);
+    }
+
+    #[test]
+    fn test_check_emoji() {
+        let key = ApiKey {
+            id: "abc".to_string(),
+            record: serde_json::from_str(
+                r#"{"name": "acme", "daily_quota": 100, "allowed_emojis": ["😊"], "max_image_bytes": 1024}"#,
+            )
+            .unwrap(),
+        };
+        assert!(key.check_emoji("😊").is_ok());
+        assert!(matches!(key.check_emoji("😢"), Err(AppError::Forbidden(_))));
+        assert!(matches!(key.check_description(), Err(AppError::Forbidden(_))));
+        assert_eq!(key.max_image_bytes(), 1024);
+    }
+}
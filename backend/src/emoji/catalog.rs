@@ -0,0 +1,156 @@
+use serde::Serialize;
+
+/// A facial emoji the service can transform towards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CatalogEmoji {
+    pub emoji: &'static str,
+    pub name: &'static str,
+    /// The facial expression the emoji stands for, as described to the model.
+    pub expression: &'static str,
+}
+
+const fn entry(emoji: &'static str, name: &'static str, expression: &'static str) -> CatalogEmoji {
+    CatalogEmoji { emoji, name, expression }
+}
+
+/// Every supported emoji, in the order the web app's emoji grid shows them.
+pub const CATALOG: &[CatalogEmoji] = &[
+    entry("😀", "Grinning", "a wide grin showing the top teeth, with relaxed open eyes"),
+    entry("😃", "Happy", "a big open-mouthed smile with wide, bright eyes"),
+    entry("😄", "Smile", "a big open-mouthed smile with eyes squinted happily"),
+    entry("😁", "Beaming", "a toothy beaming grin with smiling, squinting eyes"),
+    entry("😆", "Laughing", "laughing hard with the mouth wide open and eyes squeezed shut"),
+    entry("😅", "Sweat Smile", "a nervous, relieved grin with a bead of sweat on the brow"),
+    entry("😂", "Joy", "laughing so hard that tears of joy stream from squeezed eyes"),
+    entry("🤣", "Rolling", "helpless laughter with the head tilted, tears flowing and mouth wide open"),
+    entry("😊", "Blush", "a warm closed-mouth smile with smiling eyes and rosy cheeks"),
+    entry("😇", "Innocent", "a sweet, angelic smile with softly closed eyes"),
+    entry("🙂", "Slight Smile", "a small, polite closed-mouth smile"),
+    entry("🙃", "Upside Down", "a goofy, ironic half-smile"),
+    entry("😉", "Wink", "a playful wink of one eye with a knowing smile"),
+    entry("😌", "Relieved", "calm relief with closed eyes and a content smile"),
+    entry("😍", "Heart Eyes", "an adoring, starstruck smile with wide, infatuated eyes"),
+    entry("🥰", "Smiling Hearts", "a dreamy, affectionate smile with soft eyes and flushed cheeks"),
+    entry("😘", "Kiss", "blowing a kiss with puckered lips and a wink"),
+    entry("😗", "Kissing", "lips puckered for a kiss with open eyes"),
+    entry("😙", "Kiss Smile", "puckered lips with happily smiling eyes"),
+    entry("😚", "Kiss Closed", "puckered lips with eyes closed and blushing cheeks"),
+    entry("😋", "Yum", "savoring something delicious, smiling with the tongue licking the lips"),
+    entry("😛", "Tongue", "sticking the tongue out with open eyes"),
+    entry("😝", "Tongue Wink", "sticking the tongue out with eyes tightly squeezed shut"),
+    entry("😜", "Crazy", "a cheeky wink with the tongue sticking out"),
+    entry("🤪", "Zany", "a wild, goofy face with one eye wide, the other small and the tongue out"),
+    entry("🤨", "Raised Eyebrow", "skeptical, with one eyebrow raised and a flat mouth"),
+    entry("🧐", "Monocle", "close scrutiny with one eye squinting and the other wide"),
+    entry("🤓", "Nerd", "an eager, buck-toothed grin"),
+    entry("😎", "Cool", "a confident, relaxed smirk"),
+    entry("🤩", "Star Eyes", "thrilled and starstruck, with a huge grin and sparkling eyes"),
+    entry("🥳", "Party", "celebrating with puffed cheeks and a festive, excited look"),
+    entry("😏", "Smirk", "a sly, one-sided smirk with sideways eyes"),
+    entry("😒", "Unamused", "unimpressed, with a flat frown and eyes glancing sideways"),
+    entry("😞", "Disappointed", "downcast and disappointed, with lowered eyes and a frown"),
+    entry("😔", "Pensive", "sad and thoughtful, with eyes lowered and a small frown"),
+    entry("😟", "Worried", "worried, with raised inner eyebrows and a frown"),
+    entry("😕", "Confused", "confused, with a crooked, uncertain mouth"),
+    entry("🙁", "Frown", "a slight frown"),
+    entry("☹️", "Sad", "a deep, unhappy frown"),
+    entry("😣", "Persevere", "straining, with eyes scrunched shut and the mouth pressed tight"),
+    entry("😖", "Confounded", "distressed, with eyes squeezed shut and a scrunched, wavy mouth"),
+    entry("😫", "Tired", "exhausted and fed up, eyes shut and mouth open in a groan"),
+    entry("😩", "Weary", "weary and frustrated, brows up and mouth open in a whine"),
+    entry("🥺", "Pleading", "pleading puppy-dog eyes, large and glistening, with a small frown"),
+    entry("😢", "Cry", "sad, with a single tear rolling down the cheek"),
+    entry("😭", "Sob", "sobbing loudly with the mouth wide open and streams of tears"),
+    entry("😤", "Triumph", "huffing with pride or frustration, steam from the nose and a tight mouth"),
+    entry("😠", "Angry", "angry, with furrowed brows and a firm frown"),
+    entry("😡", "Rage", "red-faced rage, with deeply furrowed brows and a scowl"),
+    entry("🤬", "Cursing", "furious shouting with bared teeth and a red face"),
+    entry("🤯", "Exploding", "mind-blown shock, with wide eyes and an open mouth"),
+    entry("😳", "Flushed", "embarrassed, with wide eyes and flushed red cheeks"),
+    entry("🥵", "Hot", "overheated, red-faced and sweating with the tongue hanging out"),
+    entry("🥶", "Cold", "freezing, with a bluish face, clenched teeth and icy skin"),
+    entry("😱", "Scream", "screaming in fear, with hands on cheeks, mouth open wide and a pale face"),
+    entry("😨", "Fearful", "frightened, with wide eyes, raised brows and an open mouth"),
+    entry("😰", "Anxious", "anxious, with a pale forehead, sweat and an open frown"),
+    entry("😥", "Sad Sweat", "disappointed but relieved, with a small frown and a bead of sweat"),
+    entry("😓", "Sweat", "dejected, with lowered eyes and a cold sweat"),
+    entry("🤗", "Hug", "a warm, open-armed smile as if going in for a hug"),
+    entry("🤔", "Thinking", "deep in thought, with a raised brow and a hand on the chin"),
+    entry("🤭", "Hand Mouth", "giggling behind a hand over the mouth"),
+    entry("🤫", "Shush", "shushing with a finger pressed to the lips"),
+    entry("🤥", "Lying", "a guilty, fibbing look with a lengthened nose"),
+    entry("😶", "No Mouth", "speechless, with a blank face and the mouth pressed shut"),
+    entry("😶‍🌫️", "In Clouds", "foggy and absent-minded, with the face half hidden in a cloud"),
+    entry("😐", "Neutral", "a neutral, deadpan face with a straight mouth"),
+    entry("😑", "Expressionless", "expressionless, with eyes closed to lines and a flat mouth"),
+    entry("😬", "Grimace", "an awkward grimace with clenched, bared teeth"),
+    entry("🙄", "Eye Roll", "rolling the eyes upwards in exasperation"),
+    entry("😯", "Hushed", "mildly surprised, with raised brows and a small round mouth"),
+    entry("😦", "Frowning", "an open-mouthed frown of dismay"),
+    entry("😧", "Anguished", "anguished, with furrowed brows and an open, downturned mouth"),
+    entry("😮", "Open Mouth", "surprised, with an open round mouth and raised brows"),
+    entry("😮‍💨", "Exhale", "letting out a long sigh, with puffed lips and a gust of breath"),
+    entry("😲", "Astonished", "astonished, with very wide eyes and a gaping mouth"),
+    entry("🥱", "Yawn", "a big yawn with the mouth wide open and eyes half-closed"),
+    entry("😴", "Sleep", "fast asleep, with closed eyes and a slack mouth"),
+    entry("🤤", "Drool", "drooling with desire, the mouth hanging open"),
+    entry("😪", "Sleepy", "drowsy, with heavy eyelids and a relaxed mouth"),
+    entry("😵", "Dizzy", "dazed and dizzy, with unfocused eyes and an open mouth"),
+    entry("😵‍💫", "Spiral Eyes", "dazed and hypnotized, with spiraling eyes and a wobbly mouth"),
+    entry("🤐", "Zipper", "lips sealed shut in a tight, zipped line"),
+    entry("🥴", "Woozy", "woozy and tipsy, with uneven eyes and a wavy smile"),
+    entry("🤢", "Nauseated", "nauseated, with a queasy green tinge and a grimace"),
+    entry("🤮", "Vomit", "about to be sick, with cheeks puffed and the mouth open"),
+    entry("🤧", "Sneeze", "mid-sneeze, eyes scrunched shut"),
+    entry("😷", "Mask", "ill, with tired eyes above a medical face mask"),
+    entry("🤒", "Thermometer", "feverish and unwell, with a thermometer in the mouth"),
+    entry("🤕", "Bandage", "hurt and sorry for itself, with a bandaged head"),
+    entry("🤑", "Money", "money-hungry, with a greedy grin and the tongue out"),
+    entry("🤠", "Cowboy", "a cheerful, easygoing cowboy grin"),
+];
+
+/// Finds `emoji` in the catalog, ignoring emoji presentation selectors so
+/// `☹` and `☹️` are the same emoji.
+pub fn lookup(emoji: &str) -> Option<&'static CatalogEmoji> {
+    let wanted = emoji.chars().filter(|&c| c != '\u{FE0F}');
+    CATALOG
+        .iter()
+        .find(|entry| entry.emoji.chars().filter(|&c| c != '\u{FE0F}').eq(wanted.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The web grid's `FACIAL_EMOJIS`, parsed from the component source.
+    fn web_emojis() -> Vec<String> {
+        let source = include_str!("../../../web/src/components/EmojiGrid.tsx");
+        let start = source.find("FACIAL_EMOJIS = [").expect("FACIAL_EMOJIS is defined");
+        let list = &source[start..];
+        let list = &list[..list.find("];").expect("FACIAL_EMOJIS is closed")];
+        list.split('\'').skip(1).step_by(2).map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_catalog_matches_web_grid() {
+        let catalog: Vec<&str> = CATALOG.iter().map(|entry| entry.emoji).collect();
+        assert_eq!(catalog, web_emojis());
+    }
+
+    #[test]
+    fn test_catalog_entries_are_unique_and_described() {
+        for (i, entry) in CATALOG.iter().enumerate() {
+            assert!(!entry.name.is_empty() && !entry.expression.is_empty(), "{}", entry.emoji);
+            assert_eq!(lookup(entry.emoji).map(|e| e.emoji), Some(entry.emoji));
+            assert!(CATALOG[..i].iter().all(|other| other.name != entry.name), "{}", entry.name);
+        }
+    }
+
+    #[test]
+    fn test_lookup_ignores_presentation_selector() {
+        assert_eq!(lookup("☹").unwrap().emoji, "☹️");
+        assert_eq!(lookup("😊\u{FE0F}").unwrap().name, "Blush");
+        assert!(lookup("👍").is_none());
+        assert!(lookup("a").is_none());
+    }
+}
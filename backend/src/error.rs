@@ -1,9 +1,17 @@
 use worker::{Response, Result};
 use crate::models::{ErrorResponse, ErrorDetail};
 
+pub type AppResult<T> = std::result::Result<T, AppError>;
+
 #[derive(Debug)]
 pub enum AppError {
     BadRequest(String),
+    /// The `emoji` field is empty, is not an emoji, or is a malformed blend.
+    InvalidEmoji(String),
+    /// The `description` is too long or contains characters other than text.
+    InvalidDescription(String),
+    Unauthorized(String),
+    Forbidden(String),
     InternalError(String),
     RateLimitExceeded(String),
     // Image processing specific errors
@@ -14,6 +22,8 @@ pub enum AppError {
     GeminiApiError(String),
     GeminiQuotaExceeded(String),
     GeminiContentFiltered(String),
+    /// Our own screen of the `description`, reported like Gemini's filter.
+    DescriptionFiltered(String),
     GeminiInvalidRequest(String),
     GeminiTimeout(String),
     // Processing errors
@@ -24,6 +34,13 @@ pub enum AppError {
 
 impl AppError {
     pub fn to_response(&self) -> Result<Response> {
+        let (status, error_response) = self.to_error_response();
+        Response::from_json(&error_response)
+            .map(|r| r.with_status(status))
+    }
+
+    /// Returns the HTTP status and JSON body this error is reported with.
+    pub fn to_error_response(&self) -> (u16, ErrorResponse) {
         let (status, error_type, message, code, suggestion) = match self {
             AppError::BadRequest(msg) => (
                 400,
@@ -32,6 +49,34 @@ impl AppError {
                 "bad_request",
                 Some("Please check your input and try again.".to_string())
             ),
+            AppError::InvalidEmoji(msg) => (
+                400,
+                "invalid_request_error",
+                msg.clone(),
+                "invalid_emoji",
+                Some("Pick a single emoji, or blend up to three like 😂 70% + 😭 30%.".to_string())
+            ),
+            AppError::InvalidDescription(msg) => (
+                400,
+                "invalid_request_error",
+                msg.clone(),
+                "invalid_description",
+                Some("Describe the expression in a few plain words, like \"smug but tired\".".to_string())
+            ),
+            AppError::Unauthorized(msg) => (
+                401,
+                "authentication_error",
+                msg.clone(),
+                "invalid_api_key",
+                Some("Check the API key in your Authorization header, or omit the header to use the anonymous quota.".to_string())
+            ),
+            AppError::Forbidden(msg) => (
+                403,
+                "permission_error",
+                msg.clone(),
+                "forbidden",
+                Some("Contact support to change what your API key is allowed to do.".to_string())
+            ),
             AppError::InternalError(_) => (
                 500,
                 "internal_error",
@@ -44,28 +89,28 @@ impl AppError {
                 "rate_limit_error",
                 msg.clone(),
                 "rate_limit_exceeded",
-                Some("Please wait until tomorrow to make more requests.".to_string())
+                Some("Please wait until your quota resets to make more requests.".to_string())
             ),
             AppError::InvalidImageFormat(msg) => (
                 400,
                 "invalid_image_format",
                 msg.clone(),
                 "invalid_image_format",
-                Some("Please upload a valid image file (JPEG, PNG, or WebP).".to_string())
+                Some("Please upload a valid image file (JPEG, PNG, WebP, or HEIC).".to_string())
             ),
             AppError::ImageTooLarge(msg) => (
                 413,
                 "image_too_large",
                 msg.clone(),
                 "image_too_large",
-                Some("Please upload a smaller image (max 10MB).".to_string())
+                Some("Please upload a smaller image.".to_string())
             ),
             AppError::UnsupportedImageType(msg) => (
                 415,
                 "unsupported_image_type",
                 msg.clone(),
                 "unsupported_image_type",
-                Some("Please upload a JPEG, PNG, or WebP image.".to_string())
+                Some("Please upload a JPEG, PNG, WebP, or HEIC image.".to_string())
             ),
             AppError::GeminiApiError(_msg) => (
                 502,
@@ -88,6 +133,13 @@ impl AppError {
                 "gemini_content_filtered",
                 Some("Try using a different image or emoji that complies with Google's content policies.".to_string())
             ),
+            AppError::DescriptionFiltered(msg) => (
+                451,
+                "content_filtered",
+                msg.clone(),
+                "description_filtered",
+                Some("Describe only the facial expression you want, without other content or instructions.".to_string())
+            ),
             AppError::GeminiInvalidRequest(_msg) => (
                 400,
                 "ai_invalid_request",
@@ -125,96 +177,124 @@ impl AppError {
             ),
         };
 
+        let param = match self {
+            AppError::InvalidEmoji(_) => Some("emoji".to_string()),
+            AppError::InvalidDescription(_) | AppError::DescriptionFiltered(_) => Some("description".to_string()),
+            _ => None,
+        };
+
         let error_response = ErrorResponse {
             error: ErrorDetail {
                 message,
                 error_type: error_type.to_string(),
-                param: None,
+                param,
                 code: Some(code.to_string()),
                 suggestion,
             },
         };
 
-        Response::from_json(&error_response)
-            .map(|r| r.with_status(status))
+        (status, error_response)
+    }
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (name, msg) = match self {
+            AppError::BadRequest(msg) => ("BadRequest", msg),
+            AppError::InvalidEmoji(msg) => ("InvalidEmoji", msg),
+            AppError::InvalidDescription(msg) => ("InvalidDescription", msg),
+            AppError::Unauthorized(msg) => ("Unauthorized", msg),
+            AppError::Forbidden(msg) => ("Forbidden", msg),
+            AppError::InternalError(msg) => ("InternalError", msg),
+            AppError::RateLimitExceeded(msg) => ("RateLimitExceeded", msg),
+            AppError::InvalidImageFormat(msg) => ("InvalidImageFormat", msg),
+            AppError::ImageTooLarge(msg) => ("ImageTooLarge", msg),
+            AppError::UnsupportedImageType(msg) => ("UnsupportedImageType", msg),
+            AppError::GeminiApiError(msg) => ("GeminiApiError", msg),
+            AppError::GeminiQuotaExceeded(msg) => ("GeminiQuotaExceeded", msg),
+            AppError::GeminiContentFiltered(msg) => ("GeminiContentFiltered", msg),
+            AppError::DescriptionFiltered(msg) => ("DescriptionFiltered", msg),
+            AppError::GeminiInvalidRequest(msg) => ("GeminiInvalidRequest", msg),
+            AppError::GeminiTimeout(msg) => ("GeminiTimeout", msg),
+            AppError::ProcessingFailed(msg) => ("ProcessingFailed", msg),
+            AppError::NoFacesDetected(msg) => ("NoFacesDetected", msg),
+            AppError::TransformationFailed(msg) => ("TransformationFailed", msg),
+        };
+        write!(f, "{}: {}", name, msg)
     }
 }
 
+impl std::error::Error for AppError {}
+
+// Failures raised by the worker runtime itself (KV, fetch, body parsing) carry
+// no domain meaning, so they surface as internal errors.
 impl From<worker::Error> for AppError {
     fn from(err: worker::Error) -> Self {
-        let error_str = err.to_string();
-
-        if let Some(msg) = error_str.strip_prefix("AppError::BadRequest::") {
-            return AppError::BadRequest(msg.to_string());
-        }
-        if let Some(msg) = error_str.strip_prefix("AppError::InternalError::") {
-            return AppError::InternalError(msg.to_string());
-        }
-        if let Some(msg) = error_str.strip_prefix("AppError::RateLimitExceeded::") {
-            return AppError::RateLimitExceeded(msg.to_string());
-        }
-        if let Some(msg) = error_str.strip_prefix("AppError::InvalidImageFormat::") {
-            return AppError::InvalidImageFormat(msg.to_string());
-        }
-        if let Some(msg) = error_str.strip_prefix("AppError::ImageTooLarge::") {
-            return AppError::ImageTooLarge(msg.to_string());
-        }
-        if let Some(msg) = error_str.strip_prefix("AppError::UnsupportedImageType::") {
-            return AppError::UnsupportedImageType(msg.to_string());
-        }
-        if let Some(msg) = error_str.strip_prefix("AppError::GeminiApiError::") {
-            return AppError::GeminiApiError(msg.to_string());
-        }
-        if let Some(msg) = error_str.strip_prefix("AppError::GeminiQuotaExceeded::") {
-            return AppError::GeminiQuotaExceeded(msg.to_string());
-        }
-        if let Some(msg) = error_str.strip_prefix("AppError::GeminiApiError::") {
-            return AppError::GeminiApiError(msg.to_string());
-        }
-        if let Some(msg) = error_str.strip_prefix("AppError::GeminiQuotaExceeded::") {
-            return AppError::GeminiQuotaExceeded(msg.to_string());
-        }
-        if let Some(msg) = error_str.strip_prefix("AppError::GeminiContentFiltered::") {
-            return AppError::GeminiContentFiltered(msg.to_string());
-        }
-        if let Some(msg) = error_str.strip_prefix("AppError::GeminiInvalidRequest::") {
-            return AppError::GeminiInvalidRequest(msg.to_string());
-        }
-        if let Some(msg) = error_str.strip_prefix("AppError::GeminiTimeout::") {
-            return AppError::GeminiTimeout(msg.to_string());
-        }
-        if let Some(msg) = error_str.strip_prefix("AppError::ProcessingFailed::") {
-            return AppError::ProcessingFailed(msg.to_string());
-        }
-        if let Some(msg) = error_str.strip_prefix("AppError::NoFacesDetected::") {
-            return AppError::NoFacesDetected(msg.to_string());
-        }
-        if let Some(msg) = error_str.strip_prefix("AppError::TransformationFailed::") {
-            return AppError::TransformationFailed(msg.to_string());
-        }
-
-        AppError::InternalError(error_str)
+        AppError::InternalError(err.to_string())
     }
 }
 
-impl From<AppError> for worker::Error {
-    fn from(err: AppError) -> Self {
-        let encoded = match &err {
-            AppError::BadRequest(msg) => format!("AppError::BadRequest::{}", msg),
-            AppError::InternalError(msg) => format!("AppError::InternalError::{}", msg),
-            AppError::RateLimitExceeded(msg) => format!("AppError::RateLimitExceeded::{}", msg),
-            AppError::InvalidImageFormat(msg) => format!("AppError::InvalidImageFormat::{}", msg),
-            AppError::ImageTooLarge(msg) => format!("AppError::ImageTooLarge::{}", msg),
-            AppError::UnsupportedImageType(msg) => format!("AppError::UnsupportedImageType::{}", msg),
-            AppError::GeminiApiError(msg) => format!("AppError::GeminiApiError::{}", msg),
-            AppError::GeminiQuotaExceeded(msg) => format!("AppError::GeminiQuotaExceeded::{}", msg),
-            AppError::GeminiContentFiltered(msg) => format!("AppError::GeminiContentFiltered::{}", msg),
-            AppError::GeminiInvalidRequest(msg) => format!("AppError::GeminiInvalidRequest::{}", msg),
-            AppError::GeminiTimeout(msg) => format!("AppError::GeminiTimeout::{}", msg),
-            AppError::ProcessingFailed(msg) => format!("AppError::ProcessingFailed::{}", msg),
-            AppError::NoFacesDetected(msg) => format!("AppError::NoFacesDetected::{}", msg),
-            AppError::TransformationFailed(msg) => format!("AppError::TransformationFailed::{}", msg),
-        };
-        worker::Error::RustError(encoded)
+impl From<worker::kv::KvError> for AppError {
+    fn from(err: worker::kv::KvError) -> Self {
+        AppError::InternalError(format!("KV error: {}", err))
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(err: serde_json::Error) -> Self {
+        AppError::InternalError(format!("JSON error: {}", err))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_maps_to(err: AppError, status: u16, code: &str) {
+        let (actual_status, response) = err.to_error_response();
+        assert_eq!(actual_status, status, "status for {}", err);
+        assert_eq!(response.error.code.as_deref(), Some(code), "code for {}", err);
+    }
+
+    #[test]
+    fn test_every_variant_maps_to_status() {
+        let msg = || "details".to_string();
+        assert_maps_to(AppError::BadRequest(msg()), 400, "bad_request");
+        assert_maps_to(AppError::InvalidEmoji(msg()), 400, "invalid_emoji");
+        assert_maps_to(AppError::InvalidDescription(msg()), 400, "invalid_description");
+        assert_maps_to(AppError::Unauthorized(msg()), 401, "invalid_api_key");
+        assert_maps_to(AppError::Forbidden(msg()), 403, "forbidden");
+        assert_maps_to(AppError::InternalError(msg()), 500, "internal_error");
+        assert_maps_to(AppError::RateLimitExceeded(msg()), 429, "rate_limit_exceeded");
+        assert_maps_to(AppError::InvalidImageFormat(msg()), 400, "invalid_image_format");
+        assert_maps_to(AppError::ImageTooLarge(msg()), 413, "image_too_large");
+        assert_maps_to(AppError::UnsupportedImageType(msg()), 415, "unsupported_image_type");
+        assert_maps_to(AppError::GeminiApiError(msg()), 502, "gemini_api_error");
+        assert_maps_to(AppError::GeminiQuotaExceeded(msg()), 429, "gemini_quota_exceeded");
+        assert_maps_to(AppError::GeminiContentFiltered(msg()), 451, "gemini_content_filtered");
+        assert_maps_to(AppError::DescriptionFiltered(msg()), 451, "description_filtered");
+        assert_maps_to(AppError::GeminiInvalidRequest(msg()), 400, "gemini_invalid_request");
+        assert_maps_to(AppError::GeminiTimeout(msg()), 504, "gemini_timeout");
+        assert_maps_to(AppError::ProcessingFailed(msg()), 422, "processing_failed");
+        assert_maps_to(AppError::NoFacesDetected(msg()), 422, "no_faces_detected");
+        assert_maps_to(AppError::TransformationFailed(msg()), 422, "transformation_failed");
+    }
+
+    #[test]
+    fn test_internal_error_hides_details() {
+        let (_, response) = AppError::InternalError("secret stack trace".to_string()).to_error_response();
+        assert!(!response.error.message.contains("secret"));
+    }
+
+    #[test]
+    fn test_client_errors_keep_message() {
+        let (_, response) = AppError::BadRequest("Please upload an image".to_string()).to_error_response();
+        assert_eq!(response.error.message, "Please upload an image");
+    }
+
+    #[test]
+    fn test_worker_error_becomes_internal() {
+        let err = AppError::from(worker::Error::RustError("kv unavailable".to_string()));
+        assert!(matches!(err, AppError::InternalError(ref msg) if msg == "kv unavailable"));
+    }
+}
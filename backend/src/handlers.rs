@@ -1,232 +1,996 @@
-use worker::{Request, Response, RouteContext, Result};
-use crate::models::{TransformRequest, TransformResponse, TransformMetadata};
-use crate::error::AppError;
-use crate::providers::gemini::GeminiProvider;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use image::DynamicImage;
+use worker::{Env, Fetch, Request, Response, RouteContext, Result};
+use crate::models::{
+    AnimateMetadata, AnimateResponse, CompositeMetadata, EmojisResponse, ImageUpload, MetadataRemoved,
+    QuotaResponse, SubjectOutcome, TransformMetadata, TransformRequest, TransformResponse, TransformVariant,
+};
+use crate::auth::{self, ApiKey, DEFAULT_MAX_IMAGE_BYTES};
+use crate::emoji::{catalog, EmojiBlend};
+use crate::error::{AppError, AppResult};
+use crate::imaging::animate::{self, Playback};
+use crate::imaging::composite::{self, CompositeOptions};
+use crate::imaging::encode::{OutputOptions, MAX_OUTPUT_DIMENSION};
+use crate::imaging::preprocess::{self, PreprocessOptions};
+use crate::imaging::scrub::{self, MetadataKind};
+use crate::imaging::{DecodedImage, ImageFormat, MAX_IMAGE_PIXELS};
+use crate::negotiate::ResponseFormat;
+use crate::prompt::description::Description;
+use crate::prompt::subject::Subject;
+use crate::prompt::templates::{PromptConfig, PromptTemplates};
+use crate::prompt::Intensity;
+use crate::providers::{self, EditedImage, ImageEditProvider, InputImage, UnifiedEditRequest};
+use crate::rate_limit::RateLimiter;
+use crate::upload;
 use uuid::Uuid;
 
-pub async fn handle_transform(mut req: Request, ctx: RouteContext<()>) -> Result<Response> {
-    let env = ctx.env;
-    let start_time = worker::Date::now().as_millis() as u64;
-    let request_id = Uuid::new_v4().to_string();
+/// Authenticates the optional API key and sets up the limiter for the caller.
+async fn identify(req: &Request, env: &Env) -> AppResult<(Option<ApiKey>, RateLimiter)> {
+    let api_key = auth::authenticate(req, env).await?;
+    let limiter = RateLimiter::for_request(env, req, api_key.as_ref())?;
+    Ok((api_key, limiter))
+}
+
+pub async fn handle_transform(req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    let (api_key, limiter) = match identify(&req, &ctx.env).await {
+        Ok(identity) => identity,
+        Err(e) => return e.to_response(),
+    };
 
-    if let Err(rate_limit_error) = check_rate_limit(&req, &env).await {
-        return AppError::from(rate_limit_error).to_response();
+    let mut response = match transform(req, ctx.env, api_key.as_ref(), &limiter).await {
+        Ok(response) => response,
+        Err(e) => e.to_response()?,
+    };
+
+    // Reported after the request so the counters include any slot it consumed
+    if let Ok(Some(quota)) = limiter.quota().await {
+        quota.apply_headers(&mut response)?;
     }
 
-    let transform_req: TransformRequest = match req.json().await {
-        Ok(req) => req,
-        Err(e) => return AppError::BadRequest(format!("Invalid JSON in request body: {}", e)).to_response(),
+    Ok(response)
+}
+
+pub async fn handle_quota(req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    let quota = match identify(&req, &ctx.env).await {
+        Ok((_, limiter)) => limiter.quota().await,
+        Err(e) => Err(e),
     };
 
-    if transform_req.image.is_empty() {
-        return AppError::BadRequest("Please upload an image to transform".to_string()).to_response();
+    match quota {
+        Ok(Some(quota)) => {
+            let mut response = Response::from_json(&QuotaResponse {
+                limit: Some(quota.limit),
+                remaining: Some(quota.remaining),
+                reset: Some(quota.reset),
+            })?;
+            quota.apply_headers(&mut response)?;
+            Ok(response)
+        }
+        Ok(None) => Response::from_json(&QuotaResponse {
+            limit: None,
+            remaining: None,
+            reset: None,
+        }),
+        Err(e) => e.to_response(),
     }
+}
+
+/// Lists the emojis `/api/transform` accepts, with their names and expressions.
+pub fn handle_emojis(_req: Request, _ctx: RouteContext<()>) -> Result<Response> {
+    let mut response = Response::from_json(&EmojisResponse { emojis: catalog::CATALOG })?;
+    // The catalog only changes with a deploy
+    response.headers_mut().set("Cache-Control", "public, max-age=3600")?;
+    Ok(response)
+}
 
-    if transform_req.emoji.is_empty() {
-        return AppError::BadRequest("Please select an emoji for the transformation".to_string()).to_response();
+async fn transform(
+    mut req: Request,
+    env: Env,
+    api_key: Option<&ApiKey>,
+    limiter: &RateLimiter,
+) -> AppResult<Response> {
+    let start_time = worker::Date::now().as_millis();
+    let request_uuid = Uuid::new_v4();
+    let request_id = request_uuid.to_string();
+    let format = ResponseFormat::from_request(&req);
+
+    let max_image_bytes = api_key.map_or(DEFAULT_MAX_IMAGE_BYTES, ApiKey::max_image_bytes);
+    let transform_req = upload::read_transform_request(&mut req, max_image_bytes).await?;
+
+    if transform_req.image.is_empty() {
+        return Err(AppError::BadRequest("Please upload an image to transform".to_string()));
     }
 
+    let group = group_plan(&transform_req)?;
+    let (blend, description) = if group.is_empty() {
+        parse_expression(&transform_req.emoji, transform_req.description.as_deref())?
+    } else {
+        (None, None)
+    };
+
+    check_expression(
+        api_key,
+        blend.iter().chain(group.iter().map(|(_, blend)| blend)),
+        description.as_ref(),
+    )?;
+
     // Validate image format and size
-    if let Err(validation_error) = validate_image_data(&transform_req.image) {
-        return AppError::from(validation_error).to_response();
+    let image = validate_image(transform_req.image, max_image_bytes)?;
+    let output = OutputOptions::new(
+        transform_req.output_format,
+        transform_req.quality,
+        transform_req.max_dimension,
+    )?;
+    let composite_options = transform_req
+        .composite
+        .map(|layout| CompositeOptions::new(layout, transform_req.composite_padding))
+        .transpose()?;
+    // The original is drawn into the composite, so it has to be decodable here
+    if composite_options.is_some() && image.format == ImageFormat::Heic {
+        return Err(AppError::UnsupportedImageType(
+            "Composites need a JPEG, PNG or WebP image".to_string(),
+        ));
     }
 
-    let provider = match GeminiProvider::new(&env) {
-        Ok(p) => p,
-        Err(e) => return AppError::InternalError(format!("Failed to initialize Gemini provider: {}", e)).to_response(),
-    };
+    let n = variation_count(transform_req.n, max_variations(&env)?)?;
+    if n > 1 && format == ResponseFormat::Binary {
+        return Err(AppError::BadRequest(
+            "Multiple variations can only be returned as JSON".to_string(),
+        ));
+    }
+
+    let provider = providers::get_provider(&env)?;
 
-    let image_data = if transform_req.image.starts_with("data:") {
-        let parts: Vec<&str> = transform_req.image.split(',').collect();
-        if parts.len() != 2 {
-            return AppError::BadRequest("Invalid image data URL format. Expected 'data:mime/type;base64,data'".to_string()).to_response();
+    if !provider.get_supported_features().supports_edit {
+        return Err(AppError::InternalError(format!("Provider '{}' does not support image editing", provider.name())));
+    }
+
+    // Audited before preprocessing, which still needs the EXIF orientation
+    let input_metadata = scrub::find_metadata(&image);
+    let image = preprocess::preprocess(image, &PreprocessOptions::from_env(&env)?)?;
+    // Re-encoding already drops metadata; formats passed through untouched need scrubbing
+    let (image, _) = scrub::scrub(image);
+
+    let composite = match composite_options {
+        Some(options) => {
+            let original = image::load_from_memory(&image.bytes).map_err(|e| {
+                AppError::ProcessingFailed(format!("Failed to decode the image for the composite: {}", e))
+            })?;
+            let mut emojis: Vec<&str> = Vec::new();
+            for emoji in blend.iter().chain(group.iter().map(|(_, blend)| blend)).flat_map(EmojiBlend::emojis) {
+                if !emojis.contains(&emoji) {
+                    emojis.push(emoji);
+                }
+            }
+            Some((options, original, emojis))
         }
-        parts[1].to_string()
+        None => None,
+    };
+
+    let intensity = transform_req.intensity.unwrap_or_default();
+    let prompt_config = PromptConfig::from_env(&env).await?;
+    // The request id is random, so it doubles as the A/B roll
+    let templates = prompt_config.choose(request_uuid.as_u64_pair().0);
+
+    // Every variation and every subject of a group is a separate model call, so each takes a slot
+    let slots = if group.is_empty() { n } else { group.len() as u32 };
+    limiter.reserve(slots).await?;
+
+    let mut outcomes = Vec::new();
+    let edit_result = if group.is_empty() {
+        let edit_request = UnifiedEditRequest {
+            image: vec![InputImage::from_decoded(&image)],
+            prompt: templates.render(blend.as_ref(), description.as_ref(), intensity, transform_req.subject.as_ref()),
+            n,
+        };
+        provider
+            .edit_image(&edit_request)
+            .await
+            .and_then(|resp| providers::collect_candidates(resp.images.into_iter().map(scrub_output)))
     } else {
-        transform_req.image.clone()
+        edit_subjects(provider.as_ref(), templates, &image, &group, intensity, &mut outcomes)
+            .await
+            .map(|edited| vec![edited])
     };
 
-    let transformed_image = match provider.transform_image(&image_data, &transform_req.emoji).await {
-        Ok(img) => img,
-        Err(e) => {
-            // Try to extract the specific AppError from the worker::Error
-            let error_str = e.to_string();
-            if let Some(msg) = error_str.strip_prefix("AppError::GeminiContentFiltered::") {
-                return AppError::GeminiContentFiltered(msg.to_string()).to_response();
-            }
-            if let Some(msg) = error_str.strip_prefix("AppError::GeminiApiError::") {
-                return AppError::GeminiApiError(msg.to_string()).to_response();
-            }
-            if let Some(msg) = error_str.strip_prefix("AppError::GeminiQuotaExceeded::") {
-                return AppError::GeminiQuotaExceeded(msg.to_string()).to_response();
-            }
-            if let Some(msg) = error_str.strip_prefix("AppError::GeminiInvalidRequest::") {
-                return AppError::GeminiInvalidRequest(msg.to_string()).to_response();
-            }
-            if let Some(msg) = error_str.strip_prefix("AppError::GeminiTimeout::") {
-                return AppError::GeminiTimeout(msg.to_string()).to_response();
-            }
-            if let Some(msg) = error_str.strip_prefix("AppError::TransformationFailed::") {
-                return AppError::TransformationFailed(msg.to_string()).to_response();
-            }
-            // Fallback to generic error
-            return AppError::InternalError(format!("Image transformation failed: {}", e)).to_response();
+    // Fetched only once an edit has succeeded, so refused or failed requests never reach the CDN
+    let glyphs = match (&composite, &edit_result) {
+        (Some((_, _, emojis)), Ok(_)) => Some(fetch_glyphs(&env, emojis).await),
+        _ => None,
+    };
+    // Composites are drawn before the output options, which then apply to the whole picture
+    let finish = |edited: DecodedImage| match &composite {
+        Some((options, original, _)) => {
+            let glyphs = glyphs.as_ref().and_then(|glyphs| glyphs.as_deref().ok()).unwrap_or_default();
+            output.apply(composite::compose(original, &edited, *options, glyphs)?)
         }
+        None => output.apply(edited),
     };
+    let edit_result = edit_result.and_then(|edited| {
+        providers::collect_candidates(edited.into_iter().map(|(image, removed)| Ok((finish(image)?, removed))))
+    });
 
-    let processing_time_ms = worker::Date::now().as_millis() as u64 - start_time;
-
-    if let Ok(kv) = env.kv("RATE_LIMIT_KV") {
-        let client_ip = req.headers().get("CF-Connecting-IP")
-            .or_else(|_| req.headers().get("X-Forwarded-For"))
-            .or_else(|_| req.headers().get("X-Real-IP"))
-            .unwrap_or_else(|_| Some("unknown".to_string()))
-            .unwrap_or_else(|| "unknown".to_string());
+    let variants = match edit_result {
+        Ok(variants) => variants,
+        Err(e) => {
+            // Failed transformations don't count against the client's quota
+            let _ = limiter.refund(slots).await;
+            return Err(e);
+        }
+    };
+    // Nor do the variations or subjects that failed when others succeeded
+    let succeeded = if group.is_empty() {
+        variants.len()
+    } else {
+        outcomes.iter().filter(|outcome| outcome.edited).count()
+    };
+    let _ = limiter.refund(slots.saturating_sub(succeeded as u32)).await;
+
+    let mut output_metadata: Vec<MetadataKind> = variants.iter().flat_map(|(_, removed)| removed.clone()).collect();
+    output_metadata.sort();
+    output_metadata.dedup();
+
+    let processing_time_ms = worker::Date::now().as_millis() - start_time;
+
+    let metadata = TransformMetadata {
+        processing_time_ms,
+        model_version: provider.model_version(),
+        request_id,
+        input_width: image.width,
+        input_height: image.height,
+        metadata_removed: MetadataRemoved {
+            input: input_metadata,
+            output: output_metadata,
+        },
+        variations: variants.len() as u32,
+        intensity,
+        prompt_version: templates.version.clone(),
+        subject: transform_req.subject,
+        subjects: outcomes,
+        composite: composite.as_ref().map(|(options, _, _)| CompositeMetadata {
+            layout: options.layout,
+            padding: options.padding,
+            badge: glyphs.as_ref().is_some_and(|glyphs| glyphs.as_ref().is_ok_and(|glyphs| !glyphs.is_empty())),
+            badge_error: glyphs.as_ref().and_then(|glyphs| glyphs.as_ref().err().cloned()),
+        }),
+        api_key: api_key.map(|key| key.record.name.clone()),
+        tier: api_key.map(|key| key.record.tier.clone()),
+    };
 
-        if client_ip != "unknown" {
-            let date_string = worker::Date::now().to_string();
-            let today = if let Some(date_part) = date_string.split('T').next() {
-                if let Some(date_only) = date_part.split(' ').next() {
-                    date_only.to_string()
-                } else {
-                    date_part.to_string()
-                }
+    let mut variants = variants.into_iter();
+    let (first, first_removed) = variants.next().expect("collect_candidates returns at least one variant");
+
+    match format {
+        ResponseFormat::Json => {
+            let transformed_image = image_data_url(&first);
+            let images = if n > 1 {
+                std::iter::once((first, first_removed))
+                    .chain(variants)
+                    .enumerate()
+                    .map(|(index, (image, removed))| TransformVariant {
+                        index: index as u32,
+                        transformed_image: image_data_url(&image),
+                        width: image.width,
+                        height: image.height,
+                        metadata_removed: removed,
+                    })
+                    .collect()
             } else {
-                "unknown".to_string()
+                Vec::new()
             };
-            let key = format!("rate_limit:{}:{}", client_ip, today);
+            Ok(Response::from_json(&TransformResponse {
+                transformed_image,
+                images,
+                metadata,
+            })?)
+        }
+        ResponseFormat::Binary => binary_response(first, &metadata),
+    }
+}
 
-            let current_count: u32 = match kv.get(&key).text().await {
-                Ok(Some(count_str)) => count_str.parse().unwrap_or(0),
-                Ok(None) => 0,
-                Err(_) => 0,
-            };
+pub async fn handle_animate(req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    let (api_key, limiter) = match identify(&req, &ctx.env).await {
+        Ok(identity) => identity,
+        Err(e) => return e.to_response(),
+    };
 
-            let new_count = current_count + 1;
-            if let Err(_) = kv.put(&key, new_count)?.execute().await {
-            }
-        }
+    let mut response = match animate(req, ctx.env, api_key.as_ref(), &limiter).await {
+        Ok(response) => response,
+        Err(e) => e.to_response()?,
+    };
+
+    if let Ok(Some(quota)) = limiter.quota().await {
+        quota.apply_headers(&mut response)?;
     }
 
-    let response = TransformResponse {
-        transformed_image,
-        metadata: TransformMetadata {
-            processing_time_ms,
-            model_version: "gemini-2.5-flash-image-preview".to_string(),
-            request_id,
+    Ok(response)
+}
+
+const DEFAULT_ANIMATION_FRAMES: u32 = 4;
+/// Most frames an animation may have, counting the original. Every other
+/// frame is a model call, so this bounds both cost and the worker's run time.
+const MAX_ANIMATION_FRAMES: u32 = 8;
+const DEFAULT_FRAME_DELAY_MS: u32 = 250;
+/// Browsers clamp shorter GIF delays, so faster animations wouldn't play as asked.
+const MIN_FRAME_DELAY_MS: u32 = 20;
+const MAX_FRAME_DELAY_MS: u32 = 10_000;
+/// Frames are kept small by default; every one is encoded into the same file.
+const DEFAULT_ANIMATION_DIMENSION: u32 = 512;
+
+/// Morphs the face from the original to the target expression over several
+/// frames and returns them as one animation.
+///
+/// Each frame after the first is a separate edit of the original, asked to go
+/// a share of the way to the target, so the frames stay consistent with the
+/// photo rather than drifting with each edit. They are generated in parallel.
+async fn animate(
+    mut req: Request,
+    env: Env,
+    api_key: Option<&ApiKey>,
+    limiter: &RateLimiter,
+) -> AppResult<Response> {
+    let start_time = worker::Date::now().as_millis();
+    let request_uuid = Uuid::new_v4();
+    let request_id = request_uuid.to_string();
+    let format = ResponseFormat::from_request(&req);
+
+    let max_image_bytes = api_key.map_or(DEFAULT_MAX_IMAGE_BYTES, ApiKey::max_image_bytes);
+    let animate_req = upload::read_animate_request(&mut req, max_image_bytes).await?;
+
+    if animate_req.image.is_empty() {
+        return Err(AppError::BadRequest("Please upload an image to animate".to_string()));
+    }
+
+    let (blend, description) = parse_expression(&animate_req.emoji, animate_req.description.as_deref())?;
+    check_expression(api_key, blend.iter(), description.as_ref())?;
+
+    let frames = bounded("frames", animate_req.frames, DEFAULT_ANIMATION_FRAMES, 2..=MAX_ANIMATION_FRAMES)?;
+    let delay_ms = bounded(
+        "delay_ms",
+        animate_req.delay_ms,
+        DEFAULT_FRAME_DELAY_MS,
+        MIN_FRAME_DELAY_MS..=MAX_FRAME_DELAY_MS,
+    )?;
+    let max_dimension = bounded(
+        "max_dimension",
+        animate_req.max_dimension,
+        DEFAULT_ANIMATION_DIMENSION,
+        1..=MAX_OUTPUT_DIMENSION,
+    )?;
+    let playback = Playback {
+        delay_ms: delay_ms as u16,
+        loops: animate_req.loops.unwrap_or(0),
+    };
+    let animation_format = animate_req.output_format.unwrap_or_default();
+
+    let image = validate_image(animate_req.image, max_image_bytes)?;
+    // The original is the first frame, so it has to be decodable here
+    if image.format == ImageFormat::Heic {
+        return Err(AppError::UnsupportedImageType(
+            "Animations need a JPEG, PNG or WebP image".to_string(),
+        ));
+    }
+
+    let provider = providers::get_provider(&env)?;
+
+    if !provider.get_supported_features().supports_edit {
+        return Err(AppError::InternalError(format!("Provider '{}' does not support image editing", provider.name())));
+    }
+
+    let input_metadata = scrub::find_metadata(&image);
+    let image = preprocess::preprocess(image, &PreprocessOptions::from_env(&env)?)?;
+    let (image, _) = scrub::scrub(image);
+
+    let prompt_config = PromptConfig::from_env(&env).await?;
+    let templates = prompt_config.choose(request_uuid.as_u64_pair().0);
+
+    // Every generated frame is a model call, so each takes a slot
+    let steps = frames - 1;
+    limiter.reserve(steps).await?;
+
+    let input = InputImage::from_decoded(&image);
+    let edits = (1..=steps).map(|step| {
+        let request = UnifiedEditRequest {
+            image: vec![input.clone()],
+            prompt: templates.render_step(
+                blend.as_ref(),
+                description.as_ref(),
+                step * 100 / steps,
+                animate_req.subject.as_ref(),
+            ),
+            n: 1,
+        };
+        let provider = provider.as_ref();
+        async move {
+            let resp = provider.edit_image(&request).await?;
+            let image = resp.images.into_iter().next().ok_or_else(|| {
+                AppError::TransformationFailed("Provider did not return an image".to_string())
+            })?;
+            scrub_output(image)
+        }
+    });
+    let results = futures::future::join_all(edits).await;
+
+    // Frames that failed are left out of the morph; it only fails when all of them did
+    let assembled = providers::collect_candidates(results).and_then(|generated| {
+        let mut original = animate::decode_frame(&image.bytes)?;
+        if original.width().max(original.height()) > max_dimension {
+            original = original.resize(max_dimension, max_dimension, image::imageops::FilterType::Triangle);
+        }
+
+        let mut pixels = vec![original];
+        let mut removed = Vec::new();
+        for (frame, kinds) in &generated {
+            pixels.push(animate::decode_frame(&frame.bytes)?);
+            removed.extend(kinds.iter().copied());
+        }
+        removed.sort();
+        removed.dedup();
+
+        let bytes = animate::assemble(&pixels, animation_format, playback)?;
+        Ok((bytes, generated.len() as u32, removed, (pixels[0].width(), pixels[0].height())))
+    });
+
+    let (bytes, generated, output_metadata, (width, height)) = match assembled {
+        Ok(assembled) => assembled,
+        Err(e) => {
+            let _ = limiter.refund(steps).await;
+            return Err(e);
+        }
+    };
+    let _ = limiter.refund(steps - generated).await;
+
+    let metadata = AnimateMetadata {
+        processing_time_ms: worker::Date::now().as_millis() - start_time,
+        model_version: provider.model_version(),
+        request_id,
+        format: animation_format,
+        frames: generated + 1,
+        delay_ms: playback.delay_ms,
+        loops: playback.loops,
+        width,
+        height,
+        metadata_removed: MetadataRemoved {
+            input: input_metadata,
+            output: output_metadata,
         },
+        prompt_version: templates.version.clone(),
+        subject: animate_req.subject,
+        api_key: api_key.map(|key| key.record.name.clone()),
+        tier: api_key.map(|key| key.record.tier.clone()),
     };
 
-    Response::from_json(&response)
+    match format {
+        ResponseFormat::Json => Ok(Response::from_json(&AnimateResponse {
+            animation: upload::to_data_url(animation_format.mime_type(), &bytes),
+            metadata,
+        })?),
+        ResponseFormat::Binary => animation_response(bytes, &metadata),
+    }
+}
+
+/// Raw animation response, with the metadata moved to headers.
+fn animation_response(bytes: Vec<u8>, metadata: &AnimateMetadata) -> AppResult<Response> {
+    let mut response = Response::from_bytes(bytes)?;
+    let headers = response.headers_mut();
+    headers.set("Content-Type", metadata.format.mime_type())?;
+    headers.set("X-Request-Id", &metadata.request_id)?;
+    headers.set("X-Processing-Time-Ms", &metadata.processing_time_ms.to_string())?;
+    headers.set("X-Model-Version", &metadata.model_version)?;
+    headers.set("X-Prompt-Version", &metadata.prompt_version)?;
+    headers.set("X-Frames", &metadata.frames.to_string())?;
+    if let Some(subject) = &metadata.subject {
+        headers.set("X-Subject", &subject.to_string())?;
+    }
+    Ok(response)
+}
+
+/// Checks an optional numeric parameter against its allowed range.
+fn bounded(name: &str, requested: Option<u32>, default: u32, range: std::ops::RangeInclusive<u32>) -> AppResult<u32> {
+    match requested.unwrap_or(default) {
+        value if range.contains(&value) => Ok(value),
+        value => Err(AppError::BadRequest(format!(
+            "{} must be between {} and {}, got {}",
+            name,
+            range.start(),
+            range.end(),
+            value
+        ))),
+    }
 }
 
-async fn check_rate_limit(req: &Request, env: &worker::Env) -> worker::Result<()> {
-    let kv = match env.kv("RATE_LIMIT_KV") {
-        Ok(kv) => kv,
-        Err(_) => return Ok(()),
+fn image_data_url(image: &DecodedImage) -> String {
+    upload::to_data_url(image.format.mime_type(), &image.bytes)
+}
+
+/// Parses the emoji (or blend) and description of a request. A description
+/// can stand in for the emoji, but something has to say what to do.
+fn parse_expression(emoji: &str, description: Option<&str>) -> AppResult<(Option<EmojiBlend>, Option<Description>)> {
+    let description = Description::parse(description)?;
+    let blend = match (emoji.trim(), &description) {
+        ("", Some(_)) => None,
+        (emoji, _) => Some(EmojiBlend::parse(emoji)?),
     };
+    Ok((blend, description))
+}
+
+/// Checks the expression against the emojis the API key is limited to, if any.
+fn check_expression<'a>(
+    api_key: Option<&ApiKey>,
+    blends: impl IntoIterator<Item = &'a EmojiBlend>,
+    description: Option<&Description>,
+) -> AppResult<()> {
+    let Some(key) = api_key else {
+        return Ok(());
+    };
+    for emoji in blends.into_iter().flat_map(EmojiBlend::emojis) {
+        key.check_emoji(emoji)?;
+    }
+    if description.is_some() {
+        key.check_description()?;
+    }
+    Ok(())
+}
 
-    let client_ip = req.headers().get("CF-Connecting-IP")
-        .or_else(|_| req.headers().get("X-Forwarded-For"))
-        .or_else(|_| req.headers().get("X-Real-IP"))
-        .unwrap_or_else(|_| Some("unknown".to_string()))
-        .unwrap_or_else(|| "unknown".to_string());
+/// Most subjects one group request may assign emojis to.
+const MAX_GROUP_SUBJECTS: usize = 6;
 
-    if client_ip == "unknown" {
-        return Err(AppError::InternalError("Unable to determine client IP for rate limiting".to_string()).into());
+/// Validates the `subjects` of a group request, parsing each one's emoji.
+/// Empty when the request isn't one.
+fn group_plan(request: &TransformRequest) -> AppResult<Vec<(Subject, EmojiBlend)>> {
+    if request.subjects.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let has_description = request.description.as_deref().is_some_and(|d| !d.trim().is_empty());
+    if !request.emoji.trim().is_empty() || has_description || request.subject.is_some() {
+        return Err(AppError::BadRequest(
+            "subjects sets the emoji for each subject; leave out emoji, description and subject".to_string(),
+        ));
+    }
+    if request.n.is_some_and(|n| n > 1) {
+        return Err(AppError::BadRequest(
+            "A group edit returns one image; n cannot be combined with subjects".to_string(),
+        ));
+    }
+    if request.subjects.len() > MAX_GROUP_SUBJECTS {
+        return Err(AppError::BadRequest(format!(
+            "At most {} subjects can be edited at once, got {}",
+            MAX_GROUP_SUBJECTS,
+            request.subjects.len()
+        )));
     }
 
-    let date_string = worker::Date::now().to_string();
-    let today = if let Some(date_part) = date_string.split('T').next() {
-        if let Some(date_only) = date_part.split(' ').next() {
-            date_only.to_string()
-        } else {
-            date_part.to_string()
+    let mut plan: Vec<(Subject, EmojiBlend)> = Vec::with_capacity(request.subjects.len());
+    for entry in &request.subjects {
+        if plan.iter().any(|(subject, _)| *subject == entry.subject) {
+            return Err(AppError::BadRequest(format!(
+                "Subject {} appears more than once in subjects",
+                entry.subject
+            )));
         }
-    } else {
-        "unknown".to_string()
-    };
-    let key = format!("rate_limit:{}:{}", client_ip, today);
+        plan.push((entry.subject, EmojiBlend::parse(&entry.emoji)?));
+    }
+    Ok(plan)
+}
 
-    let current_count: u32 = match kv.get(&key).text().await {
-        Ok(Some(count_str)) => count_str.parse().unwrap_or(0),
-        Ok(None) => 0,
-        Err(_) => 0,
+/// Edits each subject of a group photo in turn, feeding every result into the
+/// next edit so the changes accumulate. A subject that fails is recorded in
+/// `outcomes` and left as it was; the edit only fails when every subject did.
+async fn edit_subjects(
+    provider: &dyn ImageEditProvider,
+    templates: &PromptTemplates,
+    image: &DecodedImage,
+    group: &[(Subject, EmojiBlend)],
+    intensity: Intensity,
+    outcomes: &mut Vec<SubjectOutcome>,
+) -> AppResult<(DecodedImage, Vec<MetadataKind>)> {
+    let mut edited: Option<DecodedImage> = None;
+    let mut removed = Vec::new();
+    let mut first_error = None;
+
+    for (subject, blend) in group {
+        let started = worker::Date::now().as_millis();
+        let request = UnifiedEditRequest {
+            image: vec![InputImage::from_decoded(edited.as_ref().unwrap_or(image))],
+            prompt: templates.render(Some(blend), None, intensity, Some(subject)),
+            n: 1,
+        };
+        let result = provider.edit_image(&request).await.and_then(|resp| {
+            let image = resp.images.into_iter().next().ok_or_else(|| {
+                AppError::TransformationFailed("Provider did not return an image".to_string())
+            })?;
+            scrub_output(image)
+        });
+        let processing_time_ms = worker::Date::now().as_millis() - started;
+
+        let error = match result {
+            Ok((image, kinds)) => {
+                edited = Some(image);
+                removed.extend(kinds);
+                None
+            }
+            Err(e) => {
+                let (_, response) = e.to_error_response();
+                first_error.get_or_insert(e);
+                Some(response.error.message)
+            }
+        };
+        outcomes.push(SubjectOutcome {
+            subject: *subject,
+            emoji: blend.to_string(),
+            edited: error.is_none(),
+            processing_time_ms,
+            error,
+        });
+    }
+
+    match (edited, first_error) {
+        (Some(image), _) => {
+            removed.sort();
+            removed.dedup();
+            Ok((image, removed))
+        }
+        (None, Some(e)) => Err(e),
+        (None, None) => Err(AppError::TransformationFailed(
+            "No subjects to edit".to_string(),
+        )),
+    }
+}
+
+const DEFAULT_MAX_VARIATIONS: u32 = 4;
+
+/// Reads `MAX_VARIATIONS`, the most variations one request may ask for.
+fn max_variations(env: &Env) -> AppResult<u32> {
+    let Ok(value) = env.var("MAX_VARIATIONS").map(|v| v.to_string()) else {
+        return Ok(DEFAULT_MAX_VARIATIONS);
     };
 
-    const MAX_REQUESTS_PER_DAY: u32 = 5;
+    match value.trim().parse() {
+        Ok(max) if max > 0 => Ok(max),
+        _ => Err(AppError::InternalError(format!(
+            "MAX_VARIATIONS must be a positive integer, got '{}'",
+            value
+        ))),
+    }
+}
 
-    if current_count >= MAX_REQUESTS_PER_DAY {
-        return Err(AppError::RateLimitExceeded(format!(
-            "Rate limit exceeded. You can make {} requests per day. Try again tomorrow.",
-            MAX_REQUESTS_PER_DAY
-        )).into());
+/// Validates the requested number of variations against the configured maximum.
+fn variation_count(requested: Option<u32>, max: u32) -> AppResult<u32> {
+    match requested.unwrap_or(1) {
+        n if (1..=max).contains(&n) => Ok(n),
+        n => Err(AppError::BadRequest(format!(
+            "n must be between 1 and {}, got {}",
+            max, n
+        ))),
     }
+}
 
-    Ok(())
+/// Fetches the emoji images for a composite's badge from `EMOJI_GLYPH_URL`.
+///
+/// The composite is still returned when an image can't be fetched, so the
+/// error is handed back for the response metadata rather than failing the request.
+async fn fetch_glyphs(env: &Env, emojis: &[&str]) -> std::result::Result<Vec<DynamicImage>, String> {
+    let template = env
+        .var("EMOJI_GLYPH_URL")
+        .map(|v| v.to_string())
+        .unwrap_or_else(|_| composite::DEFAULT_GLYPH_URL.to_string());
+
+    let fetches = emojis.iter().map(|emoji| {
+        let url = composite::glyph_url(&template, emoji);
+        async move {
+            let failed = |reason: String| format!("Couldn't fetch the image for {}: {}", emoji, reason);
+            let url = url.parse().map_err(|e| failed(format!("invalid EMOJI_GLYPH_URL: {}", e)))?;
+            let mut response = Fetch::Url(url).send().await.map_err(|e| failed(e.to_string()))?;
+            if response.status_code() != 200 {
+                return Err(failed(format!("HTTP {}", response.status_code())));
+            }
+            let bytes = response.bytes().await.map_err(|e| failed(e.to_string()))?;
+            image::load_from_memory(&bytes).map_err(|e| failed(e.to_string()))
+        }
+    });
+    futures::future::join_all(fetches).await.into_iter().collect()
 }
 
-fn validate_image_data(image_data: &str) -> worker::Result<()> {
-    const MAX_IMAGE_SIZE: usize = 10 * 1024 * 1024; // 10MB
+/// Checks the provider's image and strips any metadata it carries before it is returned.
+fn scrub_output(image: EditedImage) -> AppResult<(DecodedImage, Vec<MetadataKind>)> {
+    let bytes = BASE64.decode(image.data.trim()).map_err(|e| {
+        AppError::TransformationFailed(format!("Provider returned invalid image data: {}", e))
+    })?;
+    let image = DecodedImage::inspect(bytes).map_err(|e| {
+        AppError::TransformationFailed(format!("Provider returned an unreadable {} image: {}", image.mime_type, e))
+    })?;
+    Ok(scrub::scrub(image))
+}
 
-    // Check if it's a data URL
-    if !image_data.starts_with("data:") {
-        return Err(AppError::InvalidImageFormat(
-            "Image must be provided as a data URL (data:image/...)"
-                .to_string(),
-        ).into());
+/// Raw image response, with the metadata that JSON callers get in the body moved to headers.
+fn binary_response(image: DecodedImage, metadata: &TransformMetadata) -> AppResult<Response> {
+    let mut removed: Vec<&str> = metadata
+        .metadata_removed
+        .input
+        .iter()
+        .chain(&metadata.metadata_removed.output)
+        .map(|kind| kind.as_str())
+        .collect();
+    removed.sort();
+    removed.dedup();
+
+    let mut response = Response::from_bytes(image.bytes)?;
+    let headers = response.headers_mut();
+    headers.set("Content-Type", image.format.mime_type())?;
+    headers.set("X-Request-Id", &metadata.request_id)?;
+    headers.set("X-Processing-Time-Ms", &metadata.processing_time_ms.to_string())?;
+    headers.set("X-Model-Version", &metadata.model_version)?;
+    headers.set("X-Intensity", metadata.intensity.as_str())?;
+    headers.set("X-Prompt-Version", &metadata.prompt_version)?;
+    if let Some(subject) = &metadata.subject {
+        headers.set("X-Subject", &subject.to_string())?;
+    }
+    if let Some(composite) = &metadata.composite {
+        headers.set("X-Composite", composite.layout.as_str())?;
+        headers.set("X-Composite-Badge", &composite.badge.to_string())?;
+    }
+    if !removed.is_empty() {
+        headers.set("X-Metadata-Removed", &removed.join(", "))?;
+    }
+    Ok(response)
+}
+
+/// Checks an uploaded image, in whichever form it arrived.
+fn validate_image(upload: ImageUpload, max_image_bytes: usize) -> AppResult<DecodedImage> {
+    match upload {
+        ImageUpload::DataUrl(data_url) => validate_image_data(&data_url, max_image_bytes),
+        ImageUpload::Bytes { mime_type, bytes } => {
+            let declared = declared_format(&mime_type)?;
+            if bytes.len() > max_image_bytes {
+                return Err(upload::image_too_large(max_image_bytes));
+            }
+            inspect_upload(bytes, declared, &mime_type)
+        }
     }
+}
 
-    // Parse data URL
-    let parts: Vec<&str> = image_data.split(',').collect();
-    if parts.len() != 2 {
+/// Decodes a base64 image data URL, checking that the declared type matches the content.
+fn validate_image_data(image_data: &str, max_image_bytes: usize) -> AppResult<DecodedImage> {
+    let (header, data) = image_data
+        .strip_prefix("data:")
+        .ok_or_else(|| {
+            AppError::InvalidImageFormat("Image must be provided as a data URL (data:image/...)".to_string())
+        })?
+        .split_once(',')
+        .ok_or_else(|| AppError::InvalidImageFormat("Invalid image data URL format".to_string()))?;
+
+    let mut params = header.split(';');
+    let mime_type = params.next().unwrap_or_default().trim();
+    if !params.any(|p| p.trim().eq_ignore_ascii_case("base64")) {
         return Err(AppError::InvalidImageFormat(
-            "Invalid image data URL format".to_string(),
-        ).into());
+            "Image data URL must be base64 encoded".to_string(),
+        ));
     }
+    let declared = declared_format(mime_type)?;
 
-    let header = parts[0];
-    let data = parts[1];
+    // Cheap bound before decoding so oversized uploads don't get buffered twice
+    if data.len() / 4 * 3 > max_image_bytes + 3 {
+        return Err(upload::image_too_large(max_image_bytes));
+    }
 
-    // Validate MIME type
-    if !header.contains("image/") {
-        return Err(AppError::UnsupportedImageType(
-            "Only image files are supported".to_string(),
-        ).into());
+    let data: String = data.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+    let bytes = BASE64
+        .decode(data)
+        .map_err(|_| AppError::InvalidImageFormat("Invalid base64 image data".to_string()))?;
+    if bytes.len() > max_image_bytes {
+        return Err(upload::image_too_large(max_image_bytes));
     }
 
-    // Check for supported formats
-    let supported_formats = ["image/jpeg", "image/jpg", "image/png", "image/webp"];
-    let is_supported = supported_formats
-        .iter()
-        .any(|format| header.contains(format));
+    inspect_upload(bytes, declared, mime_type)
+}
 
-    if !is_supported {
+/// The supported format an upload's MIME type names.
+fn declared_format(mime_type: &str) -> AppResult<ImageFormat> {
+    if !mime_type.to_ascii_lowercase().starts_with("image/") {
         return Err(AppError::UnsupportedImageType(
-            "Unsupported image format. Please use JPEG, PNG, or WebP".to_string(),
-        ).into());
+            "Only image files are supported".to_string(),
+        ));
+    }
+
+    match ImageFormat::from_mime_type(mime_type) {
+        Some(format) if format != ImageFormat::Gif => Ok(format),
+        _ => Err(AppError::UnsupportedImageType(
+            "Unsupported image format. Please use JPEG, PNG, WebP, or HEIC".to_string(),
+        )),
     }
+}
 
-    // Check approximate size (base64 is ~33% larger than binary)
-    let approximate_binary_size = (data.len() * 3) / 4;
-    if approximate_binary_size > MAX_IMAGE_SIZE {
-        return Err(AppError::ImageTooLarge(
-            format!("Image is too large (max {}MB)", MAX_IMAGE_SIZE / (1024 * 1024)),
-        ).into());
+/// Checks the structure of an upload, that it holds the format it was declared
+/// as, and that its canvas is small enough to decode.
+fn inspect_upload(bytes: Vec<u8>, declared: ImageFormat, mime_type: &str) -> AppResult<DecodedImage> {
+    let image = DecodedImage::inspect(bytes)?;
+    if image.format != declared {
+        return Err(AppError::InvalidImageFormat(format!(
+            "Image is declared as {} but contains {} data",
+            mime_type,
+            image.format.mime_type()
+        )));
     }
 
-    // Basic base64 validation
-    if !is_valid_base64(data) {
-        return Err(AppError::InvalidImageFormat(
-            "Invalid base64 image data".to_string(),
-        ).into());
+    if u64::from(image.width) * u64::from(image.height) > MAX_IMAGE_PIXELS {
+        return Err(AppError::ImageTooLarge(format!(
+            "Image dimensions {}x{} exceed the {} megapixel limit",
+            image.width,
+            image.height,
+            MAX_IMAGE_PIXELS / 1_000_000
+        )));
     }
 
-    Ok(())
+    Ok(image)
 }
 
-fn is_valid_base64(s: &str) -> bool {
-    // Remove padding characters and check if remaining chars are valid base64
-    let s = s.trim_end_matches('=');
-    s.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/' || c == '=')
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imaging::fixtures;
+
+    fn data_url(mime_type: &str, bytes: &[u8]) -> String {
+        format!("data:{};base64,{}", mime_type, BASE64.encode(bytes))
+    }
+
+    #[test]
+    fn test_validate_image_data_accepts_supported_types() {
+        let png = validate_image_data(&data_url("image/png", &fixtures::png(32, 16)), DEFAULT_MAX_IMAGE_BYTES).unwrap();
+        assert_eq!((png.format, png.width, png.height), (ImageFormat::Png, 32, 16));
+
+        assert!(validate_image_data(&data_url("image/jpeg", &fixtures::jpeg(64, 48)), DEFAULT_MAX_IMAGE_BYTES).is_ok());
+        assert!(validate_image_data(&data_url("image/jpg", &fixtures::jpeg(64, 48)), DEFAULT_MAX_IMAGE_BYTES).is_ok());
+        assert!(validate_image_data(&data_url("image/webp", &fixtures::webp(64, 48)), DEFAULT_MAX_IMAGE_BYTES).is_ok());
+        assert!(validate_image_data(&data_url("image/heic", &fixtures::heic(64, 48)), DEFAULT_MAX_IMAGE_BYTES).is_ok());
+    }
+
+    #[test]
+    fn test_validate_image_data_errors() {
+        assert!(matches!(validate_image_data("iVBORw0KGgo=", DEFAULT_MAX_IMAGE_BYTES), Err(AppError::InvalidImageFormat(_))));
+        assert!(matches!(validate_image_data("data:image/png;base64", DEFAULT_MAX_IMAGE_BYTES), Err(AppError::InvalidImageFormat(_))));
+        assert!(matches!(validate_image_data("data:image/png,raw", DEFAULT_MAX_IMAGE_BYTES), Err(AppError::InvalidImageFormat(_))));
+        assert!(matches!(validate_image_data("data:text/plain;base64,aGk=", DEFAULT_MAX_IMAGE_BYTES), Err(AppError::UnsupportedImageType(_))));
+        assert!(matches!(validate_image_data("data:image/bmp;base64,Qk0=", DEFAULT_MAX_IMAGE_BYTES), Err(AppError::UnsupportedImageType(_))));
+        assert!(matches!(validate_image_data(&data_url("image/gif", &fixtures::gif(4, 4)), DEFAULT_MAX_IMAGE_BYTES), Err(AppError::UnsupportedImageType(_))));
+        assert!(matches!(validate_image_data("data:image/png;base64,not base64!", DEFAULT_MAX_IMAGE_BYTES), Err(AppError::InvalidImageFormat(_))));
+        // Padding in the middle of the data
+        assert!(matches!(validate_image_data("data:image/png;base64,iV==BORw0KGgo", DEFAULT_MAX_IMAGE_BYTES), Err(AppError::InvalidImageFormat(_))));
+    }
+
+    #[test]
+    fn test_validate_image_data_rejects_mismatched_content() {
+        match validate_image_data(&data_url("image/png", &fixtures::jpeg(64, 48)), DEFAULT_MAX_IMAGE_BYTES) {
+            Err(AppError::InvalidImageFormat(msg)) => {
+                assert_eq!(msg, "Image is declared as image/png but contains image/jpeg data")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_validate_image_data_rejects_truncated_content() {
+        let png = fixtures::png(32, 16);
+        let truncated = data_url("image/png", &png[..png.len() - 12]);
+        assert!(matches!(validate_image_data(&truncated, DEFAULT_MAX_IMAGE_BYTES), Err(AppError::InvalidImageFormat(_))));
+    }
+
+    #[test]
+    fn test_validate_image_data_rejects_huge_dimensions() {
+        let data = data_url("image/png", &fixtures::png(10_000, 10_000));
+        assert!(matches!(validate_image_data(&data, DEFAULT_MAX_IMAGE_BYTES), Err(AppError::ImageTooLarge(_))));
+    }
+
+    #[test]
+    fn test_validate_image_data_too_large() {
+        let data = format!("data:image/png;base64,{}", "A".repeat(14 * 1024 * 1024));
+        assert!(matches!(validate_image_data(&data, DEFAULT_MAX_IMAGE_BYTES), Err(AppError::ImageTooLarge(_))));
+    }
+
+    #[test]
+    fn test_validate_image_from_uploaded_bytes() {
+        let upload = |mime_type: &str, bytes: Vec<u8>| ImageUpload::Bytes { mime_type: mime_type.to_string(), bytes };
+        let png = fixtures::png(32, 16);
+
+        let image = validate_image(upload("image/png", png.clone()), DEFAULT_MAX_IMAGE_BYTES).unwrap();
+        assert_eq!((image.format, image.bytes), (ImageFormat::Png, png.clone()));
+        assert!(matches!(validate_image(upload("image/png", png.clone()), png.len() - 1), Err(AppError::ImageTooLarge(_))));
+        assert!(matches!(validate_image(upload("image/jpeg", png.clone()), DEFAULT_MAX_IMAGE_BYTES), Err(AppError::InvalidImageFormat(_))));
+        assert!(matches!(validate_image(upload("", png), DEFAULT_MAX_IMAGE_BYTES), Err(AppError::UnsupportedImageType(_))));
+    }
+
+    #[test]
+    fn test_validate_image_data_respects_max_bytes() {
+        let png = fixtures::png(32, 16);
+        let data = data_url("image/png", &png);
+        assert!(validate_image_data(&data, png.len()).is_ok());
+        match validate_image_data(&data, png.len() - 1) {
+            Err(AppError::ImageTooLarge(msg)) => assert_eq!(msg, format!("Image is too large (max {} bytes)", png.len() - 1)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match validate_image_data(&format!("data:image/png;base64,{}", "A".repeat(4096)), 1024) {
+            Err(AppError::ImageTooLarge(msg)) => assert_eq!(msg, "Image is too large (max 1KB)"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_variation_count() {
+        assert_eq!(variation_count(None, 4).unwrap(), 1);
+        assert_eq!(variation_count(Some(4), 4).unwrap(), 4);
+        assert!(matches!(variation_count(Some(0), 4), Err(AppError::BadRequest(_))));
+        match variation_count(Some(5), 4) {
+            Err(AppError::BadRequest(msg)) => assert_eq!(msg, "n must be between 1 and 4, got 5"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_bounded() {
+        assert_eq!(bounded("frames", None, 4, 2..=8).unwrap(), 4);
+        assert_eq!(bounded("frames", Some(8), 4, 2..=8).unwrap(), 8);
+        match bounded("frames", Some(1), 4, 2..=8) {
+            Err(AppError::BadRequest(msg)) => assert_eq!(msg, "frames must be between 2 and 8, got 1"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    fn group_request(subjects: &[(&str, &str)]) -> TransformRequest {
+        TransformRequest {
+            subjects: subjects
+                .iter()
+                .map(|(subject, emoji)| crate::models::SubjectEmoji {
+                    subject: subject.parse().unwrap(),
+                    emoji: emoji.to_string(),
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_group_plan() {
+        assert!(group_plan(&TransformRequest::default()).unwrap().is_empty());
+
+        let plan = group_plan(&group_request(&[("0", "😊"), ("0.5,0,0.5,1", "😂 + 😭")])).unwrap();
+        assert_eq!(plan[0].0, Subject::Index(0));
+        assert_eq!(plan[1].1.to_string(), "😂 50% + 😭 50%");
+    }
+
+    #[test]
+    fn test_group_plan_errors() {
+        let error = |request: TransformRequest| match group_plan(&request) {
+            Err(AppError::BadRequest(msg)) => msg,
+            other => panic!("unexpected result: {:?}", other),
+        };
+
+        assert_eq!(error(group_request(&[("1", "😊"), ("1", "😢")])), "Subject 1 appears more than once in subjects");
+        assert!(error(group_request(&[("0", "😊"); 7])).starts_with("At most 6 subjects"));
+        assert!(error(TransformRequest { emoji: "😊".to_string(), ..group_request(&[("0", "😢")]) }).contains("leave out emoji"));
+        assert!(error(TransformRequest { n: Some(2), ..group_request(&[("0", "😢")]) }).contains("n cannot be combined"));
+        assert!(matches!(group_plan(&group_request(&[("0", "👍")])), Err(AppError::InvalidEmoji(_))));
+    }
+
+    #[test]
+    fn test_check_expression() {
+        let key = |allowed: &str| ApiKey {
+            id: "abc".to_string(),
+            record: serde_json::from_str(&format!(r#"{{"name": "acme", "daily_quota": 100{}}}"#, allowed)).unwrap(),
+        };
+        let restricted = key(r#", "allowed_emojis": ["😊"]"#);
+        let smile = EmojiBlend::parse("😊").unwrap();
+        let described = Description::parse(Some("smug but tired")).unwrap();
+
+        assert!(check_expression(Some(&restricted), [&smile], None).is_ok());
+        assert!(matches!(
+            check_expression(Some(&restricted), [&EmojiBlend::parse("😊 + 😢").unwrap()], None),
+            Err(AppError::Forbidden(_))
+        ));
+        // A description could ask for anything the emoji list leaves out, alone or alongside an allowed emoji
+        assert!(matches!(check_expression(Some(&restricted), [], described.as_ref()), Err(AppError::Forbidden(_))));
+        assert!(matches!(check_expression(Some(&restricted), [&smile], described.as_ref()), Err(AppError::Forbidden(_))));
+
+        assert!(check_expression(Some(&key("")), [], described.as_ref()).is_ok());
+        assert!(check_expression(None, [], described.as_ref()).is_ok());
+    }
+}
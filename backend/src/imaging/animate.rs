@@ -0,0 +1,279 @@
+use std::io::Cursor;
+use std::str::FromStr;
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{Delay, DynamicImage, ExtendedColorType, Frame, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, AppResult};
+
+/// Quantization speed for GIF frames, from 1 (best) to 30 (fastest). Workers
+/// have little CPU time to spare, and the frames are photos that lose detail
+/// to the 256-colour palette either way.
+const GIF_SPEED: i32 = 20;
+
+/// Container an animation can be returned in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnimationFormat {
+    #[default]
+    Gif,
+    #[serde(alias = "png")]
+    Apng,
+    Webp,
+}
+
+impl AnimationFormat {
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            AnimationFormat::Gif => "image/gif",
+            AnimationFormat::Apng => "image/apng",
+            AnimationFormat::Webp => "image/webp",
+        }
+    }
+}
+
+impl FromStr for AnimationFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "gif" => Ok(AnimationFormat::Gif),
+            "apng" | "png" => Ok(AnimationFormat::Apng),
+            "webp" => Ok(AnimationFormat::Webp),
+            _ => Err("expected gif, apng, or webp".to_string()),
+        }
+    }
+}
+
+/// How the frames of an animation are played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Playback {
+    /// Time each frame is shown for, in milliseconds.
+    pub delay_ms: u16,
+    /// Times the animation plays; 0 loops forever.
+    pub loops: u16,
+}
+
+/// Encodes `frames` as one animation, scaled to the size of the first frame.
+///
+/// The frames come from separate model calls, which don't always return the
+/// size they were given, so each is stretched onto the first frame's canvas.
+pub fn assemble(frames: &[DynamicImage], format: AnimationFormat, playback: Playback) -> AppResult<Vec<u8>> {
+    let first = frames
+        .first()
+        .ok_or_else(|| AppError::ProcessingFailed("An animation needs at least one frame".to_string()))?;
+    let (width, height) = (first.width(), first.height());
+    let frames: Vec<RgbaImage> = frames
+        .iter()
+        .map(|frame| {
+            if (frame.width(), frame.height()) == (width, height) {
+                frame.to_rgba8()
+            } else {
+                frame.resize_exact(width, height, FilterType::Triangle).to_rgba8()
+            }
+        })
+        .collect();
+
+    match format {
+        AnimationFormat::Gif => encode_gif(frames, playback),
+        AnimationFormat::Apng => encode_apng(&frames, playback),
+        AnimationFormat::Webp => encode_webp(&frames, playback),
+    }
+}
+
+fn encode_error(e: impl std::fmt::Display) -> AppError {
+    AppError::ProcessingFailed(format!("Failed to encode the animation: {}", e))
+}
+
+fn encode_gif(frames: Vec<RgbaImage>, playback: Playback) -> AppResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = GifEncoder::new_with_speed(&mut bytes, GIF_SPEED);
+        // GIF counts repeats after the first play, and writes nothing for a single play
+        let repeat = match playback.loops {
+            0 => Repeat::Infinite,
+            plays => Repeat::Finite(plays - 1),
+        };
+        encoder.set_repeat(repeat).map_err(encode_error)?;
+
+        let delay = Delay::from_numer_denom_ms(u32::from(playback.delay_ms), 1);
+        encoder
+            .encode_frames(frames.into_iter().map(|frame| Frame::from_parts(frame, 0, 0, delay)))
+            .map_err(encode_error)?;
+    }
+    Ok(bytes)
+}
+
+fn encode_apng(frames: &[RgbaImage], playback: Playback) -> AppResult<Vec<u8>> {
+    let (width, height) = frames[0].dimensions();
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .set_animated(frames.len() as u32, u32::from(playback.loops))
+            .map_err(encode_error)?;
+        encoder.set_frame_delay(playback.delay_ms, 1000).map_err(encode_error)?;
+
+        let mut writer = encoder.write_header().map_err(encode_error)?;
+        for frame in frames {
+            writer.write_image_data(frame.as_raw()).map_err(encode_error)?;
+        }
+        writer.finish().map_err(encode_error)?;
+    }
+    Ok(bytes)
+}
+
+/// Animated WebP, muxed by hand: `image` only writes still WebP, so each frame
+/// is encoded losslessly on its own and its `VP8L` bitstream is wrapped in an
+/// `ANMF` frame chunk.
+fn encode_webp(frames: &[RgbaImage], playback: Playback) -> AppResult<Vec<u8>> {
+    let (width, height) = frames[0].dimensions();
+
+    let mut body = Vec::new();
+    let mut vp8x = vec![0x10 | 0x02, 0, 0, 0]; // alpha and animation flags, reserved
+    vp8x.extend_from_slice(&u24(width - 1));
+    vp8x.extend_from_slice(&u24(height - 1));
+    push_chunk(&mut body, b"VP8X", &vp8x);
+
+    let mut anim = vec![0, 0, 0, 0]; // background colour
+    anim.extend_from_slice(&playback.loops.to_le_bytes());
+    push_chunk(&mut body, b"ANIM", &anim);
+
+    for frame in frames {
+        let mut still = Vec::new();
+        WebPEncoder::new_lossless(&mut still)
+            .encode(frame.as_raw(), width, height, ExtendedColorType::Rgba8)
+            .map_err(encode_error)?;
+        let bitstream = find_chunk(&still, b"VP8L")
+            .ok_or_else(|| encode_error("the encoder wrote no VP8L bitstream"))?;
+
+        let mut anmf = Vec::with_capacity(16 + 8 + bitstream.len() + 1);
+        anmf.extend_from_slice(&u24(0)); // x offset / 2
+        anmf.extend_from_slice(&u24(0)); // y offset / 2
+        anmf.extend_from_slice(&u24(width - 1));
+        anmf.extend_from_slice(&u24(height - 1));
+        anmf.extend_from_slice(&u24(u32::from(playback.delay_ms)));
+        anmf.push(0x02); // replace rather than blend, no disposal
+        push_chunk(&mut anmf, b"VP8L", bitstream);
+        push_chunk(&mut body, b"ANMF", &anmf);
+    }
+
+    let mut bytes = Vec::with_capacity(12 + body.len());
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(4 + body.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(b"WEBP");
+    bytes.extend_from_slice(&body);
+    Ok(bytes)
+}
+
+fn u24(value: u32) -> [u8; 3] {
+    let [a, b, c, _] = value.to_le_bytes();
+    [a, b, c]
+}
+
+/// Appends a RIFF chunk, padded to an even length.
+fn push_chunk(out: &mut Vec<u8>, fourcc: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(fourcc);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+/// The payload of the first `fourcc` chunk in a WebP file.
+fn find_chunk<'a>(webp: &'a [u8], fourcc: &[u8; 4]) -> Option<&'a [u8]> {
+    let mut rest = webp.get(12..)?;
+    while rest.len() >= 8 {
+        let size = u32::from_le_bytes(rest[4..8].try_into().ok()?) as usize;
+        let data = rest.get(8..8 + size)?;
+        if &rest[..4] == fourcc {
+            return Some(data);
+        }
+        rest = rest.get(8 + size + size % 2..)?;
+    }
+    None
+}
+
+/// Decodes a frame for [`assemble`].
+pub fn decode_frame(bytes: &[u8]) -> AppResult<DynamicImage> {
+    image::load(Cursor::new(bytes), image::guess_format(bytes).map_err(decode_error)?).map_err(decode_error)
+}
+
+fn decode_error(e: image::ImageError) -> AppError {
+    AppError::ProcessingFailed(format!("Failed to decode an animation frame: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::GifDecoder;
+    use image::codecs::png::PngDecoder;
+    use image::codecs::webp::WebPDecoder;
+    use image::{AnimationDecoder, Rgba};
+
+    fn frames() -> Vec<DynamicImage> {
+        vec![
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 6, Rgba([200, 30, 30, 255]))),
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 6, Rgba([30, 200, 30, 255]))),
+            // A frame the model returned at another size
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(16, 12, Rgba([30, 30, 200, 255]))),
+        ]
+    }
+
+    const PLAYBACK: Playback = Playback { delay_ms: 120, loops: 0 };
+
+    fn check_frames<'a>(decoded: impl AnimationDecoder<'a>) {
+        let decoded = decoded.into_frames().collect_frames().unwrap();
+        assert_eq!(decoded.len(), 3);
+        for frame in &decoded {
+            assert_eq!(frame.buffer().dimensions(), (8, 6));
+            assert_eq!(frame.delay().numer_denom_ms(), (120, 1));
+        }
+        assert_eq!(decoded[2].buffer().get_pixel(4, 3)[2], 200);
+    }
+
+    #[test]
+    fn test_gif() {
+        let gif = assemble(&frames(), AnimationFormat::Gif, PLAYBACK).unwrap();
+        assert!(gif.starts_with(b"GIF89a"));
+        check_frames(GifDecoder::new(Cursor::new(gif)).unwrap());
+    }
+
+    #[test]
+    fn test_apng() {
+        let apng = assemble(&frames(), AnimationFormat::Apng, PLAYBACK).unwrap();
+        let decoder = PngDecoder::new(Cursor::new(apng)).unwrap();
+        assert!(decoder.is_apng().unwrap());
+        check_frames(decoder.apng().unwrap());
+    }
+
+    #[test]
+    fn test_webp() {
+        let webp = assemble(&frames(), AnimationFormat::Webp, Playback { delay_ms: 120, loops: 3 }).unwrap();
+        assert_eq!(find_chunk(&webp, b"ANIM").unwrap(), [0, 0, 0, 0, 3, 0]);
+        let decoder = WebPDecoder::new(Cursor::new(webp)).unwrap();
+        assert!(decoder.has_animation());
+        check_frames(decoder);
+    }
+
+    #[test]
+    fn test_decode_frame() {
+        let gif = assemble(&frames()[..1], AnimationFormat::Gif, PLAYBACK).unwrap();
+        assert_eq!(decode_frame(&gif).unwrap().width(), 8);
+        assert!(matches!(decode_frame(b"nope"), Err(AppError::ProcessingFailed(_))));
+    }
+
+    #[test]
+    fn test_animation_format_parsing() {
+        assert_eq!("GIF".parse::<AnimationFormat>().unwrap(), AnimationFormat::Gif);
+        assert_eq!("png".parse::<AnimationFormat>().unwrap(), AnimationFormat::Apng);
+        assert!("mp4".parse::<AnimationFormat>().is_err());
+        assert_eq!(serde_json::from_str::<AnimationFormat>(r#""webp""#).unwrap(), AnimationFormat::Webp);
+    }
+}
@@ -0,0 +1,319 @@
+use std::str::FromStr;
+
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use super::encode::{self, DEFAULT_JPEG_QUALITY};
+use super::{DecodedImage, ImageFormat};
+use crate::error::{AppError, AppResult};
+
+pub const DEFAULT_COMPOSITE_PADDING: u32 = 16;
+pub const MAX_COMPOSITE_PADDING: u32 = 256;
+
+/// Where the emoji images for the badge come from, with `{code}` standing for
+/// the emoji's [`twemoji_code`]. Overridden by the `EMOJI_GLYPH_URL` variable.
+pub const DEFAULT_GLYPH_URL: &str = "https://cdn.jsdelivr.net/gh/jdecked/twemoji@15.1.0/assets/72x72/{code}.png";
+
+const BACKGROUND: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const SHADOW: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+/// How the original and transformed images are arranged in a composite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompositeLayout {
+    /// Original on the left, transformed on the right.
+    SideBySide,
+    /// One image split along the diagonal, the original above it and the
+    /// transformed image below.
+    Diagonal,
+}
+
+impl CompositeLayout {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CompositeLayout::SideBySide => "side_by_side",
+            CompositeLayout::Diagonal => "diagonal",
+        }
+    }
+}
+
+impl FromStr for CompositeLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().replace('-', "_").as_str() {
+            "side_by_side" => Ok(CompositeLayout::SideBySide),
+            "diagonal" => Ok(CompositeLayout::Diagonal),
+            _ => Err("expected side_by_side or diagonal".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompositeOptions {
+    pub layout: CompositeLayout,
+    /// Border around and between the images, in pixels of the original.
+    pub padding: u32,
+}
+
+impl CompositeOptions {
+    pub fn new(layout: CompositeLayout, padding: Option<u32>) -> AppResult<Self> {
+        let padding = padding.unwrap_or(DEFAULT_COMPOSITE_PADDING);
+        if padding > MAX_COMPOSITE_PADDING {
+            return Err(AppError::BadRequest(format!(
+                "composite_padding must be between 0 and {}, got {}",
+                MAX_COMPOSITE_PADDING, padding
+            )));
+        }
+        Ok(Self { layout, padding })
+    }
+}
+
+/// A rectangle of the canvas: x, y, width, height.
+type Rect = (u32, u32, u32, u32);
+
+/// Composites the transformed image with the original and encodes the result
+/// as PNG, leaving any conversion to the client's output options.
+pub fn compose(
+    original: &DynamicImage,
+    transformed: &DecodedImage,
+    options: CompositeOptions,
+    glyphs: &[DynamicImage],
+) -> AppResult<DecodedImage> {
+    let transformed = image::load_from_memory(&transformed.bytes).map_err(|e| {
+        AppError::ProcessingFailed(format!("Failed to decode the transformed image: {}", e))
+    })?;
+    let pixels = DynamicImage::ImageRgba8(render(original, &transformed, options, glyphs));
+    Ok(DecodedImage {
+        bytes: encode::encode(&pixels, ImageFormat::Png, DEFAULT_JPEG_QUALITY)?,
+        format: ImageFormat::Png,
+        width: pixels.width(),
+        height: pixels.height(),
+    })
+}
+
+/// Renders the before/after composite at the original's scale, with the
+/// `glyphs` of the emojis drawn on a badge in the corner of the transformed side.
+pub fn render(
+    original: &DynamicImage,
+    transformed: &DynamicImage,
+    options: CompositeOptions,
+    glyphs: &[DynamicImage],
+) -> RgbaImage {
+    let (width, height) = (original.width(), original.height());
+    let pad = options.padding;
+
+    let (mut canvas, badge_area) = match options.layout {
+        CompositeLayout::SideBySide => {
+            // The model doesn't always keep the size, so match the heights
+            let scaled_width = (u64::from(transformed.width()) * u64::from(height)
+                / u64::from(transformed.height().max(1))) as u32;
+            let transformed = transformed.resize_exact(scaled_width.max(1), height, FilterType::Triangle);
+
+            let mut canvas = RgbaImage::from_pixel(3 * pad + width + scaled_width.max(1), 2 * pad + height, BACKGROUND);
+            imageops::overlay(&mut canvas, &original.to_rgba8(), i64::from(pad), i64::from(pad));
+            imageops::overlay(&mut canvas, &transformed.to_rgba8(), i64::from(2 * pad + width), i64::from(pad));
+            (canvas, (2 * pad + width, pad, scaled_width.max(1), height))
+        }
+        CompositeLayout::Diagonal => {
+            let original = original.to_rgba8();
+            let transformed = transformed.resize_exact(width, height, FilterType::Triangle).to_rgba8();
+            // Divider between the halves, thin enough to keep both faces visible
+            let divider = (width.min(height) as f32 / 150.0).max(2.0);
+
+            let mut canvas = RgbaImage::from_pixel(2 * pad + width, 2 * pad + height, BACKGROUND);
+            for (x, y, pixel) in original.enumerate_pixels() {
+                // Signed distance from the line joining the bottom-left and top-right corners
+                let offset = (x as f32 + 0.5) / width as f32 + (y as f32 + 0.5) / height as f32 - 1.0;
+                let distance = offset * (width as f32 * height as f32) / (width as f32).hypot(height as f32);
+                let value = if distance.abs() < divider / 2.0 {
+                    BACKGROUND
+                } else if distance < 0.0 {
+                    *pixel
+                } else {
+                    *transformed.get_pixel(x, y)
+                };
+                canvas.put_pixel(x + pad, y + pad, value);
+            }
+            (canvas, (pad, pad, width, height))
+        }
+    };
+
+    draw_badge(&mut canvas, badge_area, glyphs);
+    canvas
+}
+
+/// Draws the glyphs on a white pill in the bottom-right corner of `area`.
+fn draw_badge(canvas: &mut RgbaImage, area: Rect, glyphs: &[DynamicImage]) {
+    if glyphs.is_empty() {
+        return;
+    }
+
+    let (x, y, width, height) = area;
+    let diameter = (width.min(height) / 5).max(40).min(width.min(height));
+    let glyph_size = diameter * 5 / 8;
+    let gap = diameter / 8;
+    let margin = diameter / 6;
+    // Too thin for a legible badge
+    if glyph_size == 0 {
+        return;
+    }
+    // A blend or a group can have more emojis than fit across a narrow image
+    let fits = (width.saturating_sub(2 * margin + diameter) / (glyph_size + gap) + 1) as usize;
+    let glyphs = &glyphs[..glyphs.len().min(fits)];
+    let extra = (glyph_size + gap) * (glyphs.len() as u32 - 1);
+
+    let radius = diameter as f32 / 2.0;
+    let right = (x + width) as f32 - margin as f32 - radius;
+    let left = (right - extra as f32).max(x as f32 + radius);
+    let center_y = (y + height) as f32 - margin as f32 - radius;
+
+    fill_pill(canvas, (left + 1.0, center_y + 2.0), right + 1.0, radius + 1.0, SHADOW, 0.2);
+    fill_pill(canvas, (left, center_y), right, radius, BACKGROUND, 1.0);
+
+    for (i, glyph) in glyphs.iter().enumerate() {
+        let glyph = glyph.resize(glyph_size, glyph_size, FilterType::Triangle).to_rgba8();
+        let glyph_x = left + (i as u32 * (glyph_size + gap)) as f32 - glyph.width() as f32 / 2.0;
+        let glyph_y = center_y - glyph.height() as f32 / 2.0;
+        imageops::overlay(canvas, &glyph, glyph_x.round() as i64, glyph_y.round() as i64);
+    }
+}
+
+/// Blends `color` into a horizontal pill: the circles of `radius` around
+/// `(left, y)` and `(right, y)` and everything between them. Edges are
+/// anti-aliased over one pixel.
+fn fill_pill(canvas: &mut RgbaImage, (left, center_y): (f32, f32), right: f32, radius: f32, color: Rgba<u8>, opacity: f32) {
+    let x_range = (left - radius).floor().max(0.0) as u32..((right + radius).ceil() as u32).min(canvas.width());
+    let y_range = (center_y - radius).floor().max(0.0) as u32..((center_y + radius).ceil() as u32).min(canvas.height());
+
+    for py in y_range {
+        for px in x_range.clone() {
+            let (fx, fy) = (px as f32 + 0.5, py as f32 + 0.5);
+            let nearest_x = fx.clamp(left, right);
+            let distance = (fx - nearest_x).hypot(fy - center_y);
+            let coverage = (radius - distance + 0.5).clamp(0.0, 1.0) * opacity;
+            if coverage > 0.0 {
+                let pixel = canvas.get_pixel_mut(px, py);
+                for channel in 0..3 {
+                    let blended = f32::from(pixel[channel]) * (1.0 - coverage) + f32::from(color[channel]) * coverage;
+                    pixel[channel] = blended.round() as u8;
+                }
+            }
+        }
+    }
+}
+
+/// The Twemoji file name of `emoji`: its code points in hex, joined by `-`.
+/// Presentation selectors are dropped except in ZWJ sequences, as Twemoji does.
+pub fn twemoji_code(emoji: &str) -> String {
+    let keep_selectors = emoji.contains('\u{200D}');
+    emoji
+        .chars()
+        .filter(|&c| keep_selectors || c != '\u{FE0F}')
+        .map(|c| format!("{:x}", u32::from(c)))
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// The URL of `emoji`'s image in a glyph URL template.
+pub fn glyph_url(template: &str, emoji: &str) -> String {
+    template.replace("{code}", &twemoji_code(emoji))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgba<u8> = Rgba([220, 20, 20, 255]);
+    const BLUE: Rgba<u8> = Rgba([20, 20, 220, 255]);
+
+    fn solid(width: u32, height: u32, color: Rgba<u8>) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, color))
+    }
+
+    fn options(layout: CompositeLayout, padding: u32) -> CompositeOptions {
+        CompositeOptions::new(layout, Some(padding)).unwrap()
+    }
+
+    #[test]
+    fn test_side_by_side() {
+        // The transformed image came back at twice the size
+        let canvas = render(&solid(40, 30, RED), &solid(80, 60, BLUE), options(CompositeLayout::SideBySide, 10), &[]);
+        assert_eq!(canvas.dimensions(), (10 + 40 + 10 + 40 + 10, 10 + 30 + 10));
+        assert_eq!(*canvas.get_pixel(5, 5), BACKGROUND);
+        assert_eq!(*canvas.get_pixel(20, 20), RED);
+        assert_eq!(*canvas.get_pixel(55, 20), BACKGROUND);
+        assert_eq!(*canvas.get_pixel(80, 20), BLUE);
+    }
+
+    #[test]
+    fn test_diagonal_split() {
+        let canvas = render(&solid(60, 60, RED), &solid(60, 60, BLUE), options(CompositeLayout::Diagonal, 0), &[]);
+        assert_eq!(canvas.dimensions(), (60, 60));
+        assert_eq!(*canvas.get_pixel(5, 5), RED);
+        assert_eq!(*canvas.get_pixel(55, 55), BLUE);
+        // On the divider
+        assert_eq!(*canvas.get_pixel(30, 29), BACKGROUND);
+    }
+
+    #[test]
+    fn test_badge_is_drawn_on_transformed_side() {
+        let glyph = solid(72, 72, Rgba([250, 200, 0, 255]));
+        let canvas = render(
+            &solid(200, 200, RED),
+            &solid(200, 200, BLUE),
+            options(CompositeLayout::SideBySide, 0),
+            &[glyph],
+        );
+        // Badge diameter is 40 with a margin of 6, so its centre is at (374, 174)
+        assert_eq!(*canvas.get_pixel(374, 174), Rgba([250, 200, 0, 255]));
+        // Pill edge around the glyph is white, and the original side is untouched
+        assert_eq!(*canvas.get_pixel(374, 157), BACKGROUND);
+        assert_eq!(*canvas.get_pixel(174, 174), RED);
+    }
+
+    #[test]
+    fn test_badge_is_skipped_on_thin_images() {
+        let glyphs = [solid(72, 72, Rgba([250, 200, 0, 255]))];
+        for layout in [CompositeLayout::Diagonal, CompositeLayout::SideBySide] {
+            let canvas = render(&solid(1, 40, RED), &solid(1, 40, BLUE), options(layout, 0), &glyphs);
+            assert_eq!(canvas.height(), 40);
+            assert!(!canvas.pixels().any(|pixel| *pixel == Rgba([250, 200, 0, 255])));
+        }
+    }
+
+    #[test]
+    fn test_options() {
+        assert_eq!("side-by-side".parse::<CompositeLayout>().unwrap(), CompositeLayout::SideBySide);
+        assert_eq!("Diagonal".parse::<CompositeLayout>().unwrap(), CompositeLayout::Diagonal);
+        assert!("grid".parse::<CompositeLayout>().is_err());
+        assert_eq!(serde_json::to_string(&CompositeLayout::SideBySide).unwrap(), r#""side_by_side""#);
+        assert_eq!(CompositeOptions::new(CompositeLayout::Diagonal, None).unwrap().padding, DEFAULT_COMPOSITE_PADDING);
+        assert!(matches!(
+            CompositeOptions::new(CompositeLayout::Diagonal, Some(300)),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_twemoji_code() {
+        assert_eq!(twemoji_code("😊"), "1f60a");
+        assert_eq!(twemoji_code("☹️"), "2639");
+        assert_eq!(twemoji_code("😶\u{200D}🌫\u{FE0F}"), "1f636-200d-1f32b-fe0f");
+        assert_eq!(
+            glyph_url(DEFAULT_GLYPH_URL, "😢"),
+            "https://cdn.jsdelivr.net/gh/jdecked/twemoji@15.1.0/assets/72x72/1f622.png"
+        );
+    }
+
+    #[test]
+    fn test_compose() {
+        let bytes = encode::encode(&solid(20, 10, BLUE), ImageFormat::Jpeg, DEFAULT_JPEG_QUALITY).unwrap();
+        let transformed = DecodedImage::inspect(bytes).unwrap();
+        let composite = compose(&solid(20, 10, RED), &transformed, options(CompositeLayout::SideBySide, 4), &[]).unwrap();
+        assert_eq!(composite.format, ImageFormat::Png);
+        assert_eq!((composite.width, composite.height), (4 + 20 + 4 + 20 + 4, 4 + 10 + 4));
+        assert_eq!(DecodedImage::inspect(composite.bytes).unwrap().width, 52);
+    }
+}
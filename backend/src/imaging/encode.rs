@@ -0,0 +1,226 @@
+use std::io::Cursor;
+use std::str::FromStr;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat as CodecFormat};
+use serde::{Deserialize, Serialize};
+
+use super::{DecodedImage, ImageFormat};
+use crate::error::{AppError, AppResult};
+
+pub const DEFAULT_JPEG_QUALITY: u8 = 90;
+/// Largest `max_dimension` a client may ask for; outputs are never upscaled anyway.
+pub const MAX_OUTPUT_DIMENSION: u32 = 4096;
+
+/// Formats a transformed image can be returned in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Png,
+    #[serde(alias = "jpg")]
+    Jpeg,
+    Webp,
+}
+
+impl OutputFormat {
+    pub fn image_format(self) -> ImageFormat {
+        match self {
+            OutputFormat::Png => ImageFormat::Png,
+            OutputFormat::Jpeg => ImageFormat::Jpeg,
+            OutputFormat::Webp => ImageFormat::WebP,
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "png" => Ok(OutputFormat::Png),
+            "jpeg" | "jpg" => Ok(OutputFormat::Jpeg),
+            "webp" => Ok(OutputFormat::Webp),
+            _ => Err("expected png, jpeg, or webp".to_string()),
+        }
+    }
+}
+
+/// Client requested shape of the transformed image.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutputOptions {
+    pub format: Option<OutputFormat>,
+    /// JPEG quality from 1 to 100. PNG and WebP output is lossless and ignores it.
+    pub quality: Option<u8>,
+    pub max_dimension: Option<u32>,
+}
+
+impl OutputOptions {
+    pub fn new(format: Option<OutputFormat>, quality: Option<u8>, max_dimension: Option<u32>) -> AppResult<Self> {
+        if let Some(quality) = quality {
+            if !(1..=100).contains(&quality) {
+                return Err(AppError::BadRequest(format!(
+                    "quality must be between 1 and 100, got {}",
+                    quality
+                )));
+            }
+        }
+
+        if let Some(max_dimension) = max_dimension {
+            if !(1..=MAX_OUTPUT_DIMENSION).contains(&max_dimension) {
+                return Err(AppError::BadRequest(format!(
+                    "max_dimension must be between 1 and {}, got {}",
+                    MAX_OUTPUT_DIMENSION, max_dimension
+                )));
+            }
+        }
+
+        Ok(Self { format, quality, max_dimension })
+    }
+
+    /// Converts the provider's image to the requested format and size.
+    ///
+    /// The image is returned untouched when it already satisfies every option,
+    /// so the default request never pays for a decode and re-encode.
+    pub fn apply(&self, image: DecodedImage) -> AppResult<DecodedImage> {
+        let target = self.format.map_or(image.format, OutputFormat::image_format);
+        let oversized = self
+            .max_dimension
+            .is_some_and(|max| image.width.max(image.height) > max);
+        let requality = self.quality.is_some() && target == ImageFormat::Jpeg;
+
+        if target == image.format && !oversized && !requality {
+            return Ok(image);
+        }
+
+        let codec = codec_format(image.format).ok_or_else(|| {
+            AppError::ProcessingFailed(format!(
+                "{} images cannot be converted",
+                image.format.name()
+            ))
+        })?;
+        let mut pixels = image::load_from_memory_with_format(&image.bytes, codec).map_err(|e| {
+            AppError::ProcessingFailed(format!("Failed to decode the transformed image: {}", e))
+        })?;
+
+        if let Some(max) = self.max_dimension.filter(|_| oversized) {
+            pixels = pixels.resize(max, max, FilterType::Triangle);
+        }
+
+        let quality = self.quality.unwrap_or(DEFAULT_JPEG_QUALITY);
+        let bytes = encode(&pixels, target, quality)?;
+        Ok(DecodedImage {
+            bytes,
+            format: target,
+            width: pixels.width(),
+            height: pixels.height(),
+        })
+    }
+}
+
+/// The `image` codec for `format`, or `None` when it cannot be decoded in pure Rust.
+pub(super) fn codec_format(format: ImageFormat) -> Option<CodecFormat> {
+    match format {
+        ImageFormat::Jpeg => Some(CodecFormat::Jpeg),
+        ImageFormat::Png => Some(CodecFormat::Png),
+        ImageFormat::WebP => Some(CodecFormat::WebP),
+        ImageFormat::Gif => Some(CodecFormat::Gif),
+        ImageFormat::Heic => None,
+    }
+}
+
+/// Encodes `pixels` as `format`. JPEG drops any alpha channel; the other
+/// formats are lossless. Nothing but pixel data is written.
+pub fn encode(pixels: &DynamicImage, format: ImageFormat, jpeg_quality: u8) -> AppResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let result = match format {
+        ImageFormat::Jpeg => pixels
+            .to_rgb8()
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, jpeg_quality)),
+        ImageFormat::Png => pixels.write_to(&mut Cursor::new(&mut bytes), CodecFormat::Png),
+        ImageFormat::WebP => {
+            let rgba = pixels.to_rgba8();
+            rgba.write_with_encoder(WebPEncoder::new_lossless(&mut bytes))
+        }
+        ImageFormat::Gif | ImageFormat::Heic => {
+            return Err(AppError::ProcessingFailed(format!(
+                "Encoding {} images is not supported",
+                format.name()
+            )))
+        }
+    };
+
+    result.map_err(|e| AppError::ProcessingFailed(format!("Failed to encode the image: {}", e)))?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    fn png(width: u32, height: u32) -> DecodedImage {
+        let pixels = DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, Rgba([10, 200, 30, 255])));
+        DecodedImage::inspect(encode(&pixels, ImageFormat::Png, DEFAULT_JPEG_QUALITY).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_output_format_parsing() {
+        assert_eq!("PNG".parse::<OutputFormat>().unwrap(), OutputFormat::Png);
+        assert_eq!("jpg".parse::<OutputFormat>().unwrap(), OutputFormat::Jpeg);
+        assert_eq!("webp".parse::<OutputFormat>().unwrap(), OutputFormat::Webp);
+        assert!("gif".parse::<OutputFormat>().is_err());
+        assert_eq!(serde_json::from_str::<OutputFormat>(r#""jpg""#).unwrap(), OutputFormat::Jpeg);
+    }
+
+    #[test]
+    fn test_options_validation() {
+        assert!(OutputOptions::new(None, Some(75), Some(512)).is_ok());
+        assert!(matches!(OutputOptions::new(None, Some(0), None), Err(AppError::BadRequest(_))));
+        assert!(matches!(OutputOptions::new(None, Some(101), None), Err(AppError::BadRequest(_))));
+        assert!(matches!(OutputOptions::new(None, None, Some(0)), Err(AppError::BadRequest(_))));
+        assert!(matches!(OutputOptions::new(None, None, Some(MAX_OUTPUT_DIMENSION + 1)), Err(AppError::BadRequest(_))));
+    }
+
+    #[test]
+    fn test_default_options_keep_provider_bytes() {
+        let image = png(16, 16);
+        let result = OutputOptions::default().apply(image.clone()).unwrap();
+        assert_eq!(result.bytes, image.bytes);
+    }
+
+    #[test]
+    fn test_transcodes_each_format() {
+        for format in [OutputFormat::Png, OutputFormat::Jpeg, OutputFormat::Webp] {
+            let options = OutputOptions::new(Some(format), None, None).unwrap();
+            let result = options.apply(png(16, 8)).unwrap();
+            let reparsed = DecodedImage::inspect(result.bytes).unwrap();
+            assert_eq!(reparsed.format, format.image_format());
+            assert_eq!((reparsed.width, reparsed.height), (16, 8));
+        }
+    }
+
+    #[test]
+    fn test_max_dimension_downscales_only() {
+        let options = OutputOptions::new(None, None, Some(8)).unwrap();
+        let result = options.apply(png(32, 16)).unwrap();
+        assert_eq!((result.width, result.height), (8, 4));
+        assert_eq!(result.format, ImageFormat::Png);
+
+        let small = png(4, 4);
+        assert_eq!(options.apply(small.clone()).unwrap().bytes, small.bytes);
+    }
+
+    #[test]
+    fn test_quality_changes_jpeg_size() {
+        let pixels = DynamicImage::ImageRgba8(RgbaImage::from_fn(64, 64, |x, y| {
+            Rgba([(x * 4) as u8, (y * 4) as u8, ((x ^ y) * 4) as u8, 255])
+        }));
+        let source = DecodedImage::inspect(encode(&pixels, ImageFormat::Png, DEFAULT_JPEG_QUALITY).unwrap()).unwrap();
+
+        let low = OutputOptions::new(Some(OutputFormat::Jpeg), Some(10), None).unwrap().apply(source.clone()).unwrap();
+        let high = OutputOptions::new(Some(OutputFormat::Jpeg), Some(100), None).unwrap().apply(source).unwrap();
+        assert!(low.bytes.len() < high.bytes.len());
+    }
+}
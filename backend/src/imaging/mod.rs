@@ -0,0 +1,491 @@
+pub mod animate;
+pub mod composite;
+pub mod encode;
+pub mod preprocess;
+pub mod scrub;
+
+use crate::error::{AppError, AppResult};
+
+/// Image container formats recognised from their leading bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+    WebP,
+    Gif,
+    Heic,
+}
+
+impl ImageFormat {
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Png => "image/png",
+            ImageFormat::WebP => "image/webp",
+            ImageFormat::Gif => "image/gif",
+            ImageFormat::Heic => "image/heic",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "JPEG",
+            ImageFormat::Png => "PNG",
+            ImageFormat::WebP => "WebP",
+            ImageFormat::Gif => "GIF",
+            ImageFormat::Heic => "HEIC",
+        }
+    }
+
+    /// Maps a declared MIME type, including common aliases, to a format.
+    pub fn from_mime_type(mime_type: &str) -> Option<Self> {
+        match mime_type.trim().to_ascii_lowercase().as_str() {
+            "image/jpeg" | "image/jpg" | "image/pjpeg" => Some(ImageFormat::Jpeg),
+            "image/png" | "image/apng" => Some(ImageFormat::Png),
+            "image/webp" => Some(ImageFormat::WebP),
+            "image/gif" => Some(ImageFormat::Gif),
+            "image/heic" | "image/heif" | "image/heic-sequence" | "image/heif-sequence" => {
+                Some(ImageFormat::Heic)
+            }
+            _ => None,
+        }
+    }
+
+    /// Detects the format from the file signature, ignoring whatever the client declared.
+    pub fn sniff(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(ImageFormat::Jpeg)
+        } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(ImageFormat::Png)
+        } else if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+            Some(ImageFormat::WebP)
+        } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+            Some(ImageFormat::Gif)
+        } else if bytes.len() >= 12 && &bytes[4..8] == b"ftyp" && is_heif_brand(&bytes[8..12]) {
+            Some(ImageFormat::Heic)
+        } else {
+            None
+        }
+    }
+}
+
+/// Largest canvas accepted, checked before anything decodes pixels. Uploads
+/// are decoded to RGBA in full before they are downscaled, and 16 megapixels
+/// is 64MB of that, which leaves room in a Workers isolate's 128MB.
+pub const MAX_IMAGE_PIXELS: u64 = 16_000_000;
+
+/// A fully decoded upload whose structure has been checked end to end.
+#[derive(Debug, Clone)]
+pub struct DecodedImage {
+    pub bytes: Vec<u8>,
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl DecodedImage {
+    /// Sniffs and parses `bytes`, rejecting unknown, truncated and polyglot files.
+    ///
+    /// Only the container structure is checked: every chunk, segment or box must
+    /// fit in the data, and nothing may follow the format's end marker.
+    pub fn inspect(bytes: Vec<u8>) -> AppResult<Self> {
+        let format = ImageFormat::sniff(&bytes).ok_or_else(|| {
+            AppError::UnsupportedImageType("The file is not a recognised image format".to_string())
+        })?;
+
+        let (width, height) = match format {
+            ImageFormat::Jpeg => jpeg_dimensions(&bytes),
+            ImageFormat::Png => png_dimensions(&bytes),
+            ImageFormat::WebP => webp_dimensions(&bytes),
+            ImageFormat::Gif => gif_dimensions(&bytes),
+            ImageFormat::Heic => heic_dimensions(&bytes),
+        }
+        .ok_or_else(|| {
+            AppError::InvalidImageFormat(format!(
+                "The {} image is truncated or corrupt",
+                format.name()
+            ))
+        })?;
+
+        if width == 0 || height == 0 {
+            return Err(AppError::InvalidImageFormat(format!(
+                "The {} image has no pixels",
+                format.name()
+            )));
+        }
+
+        Ok(Self { bytes, format, width, height })
+    }
+}
+
+fn is_heif_brand(brand: &[u8]) -> bool {
+    matches!(
+        brand,
+        b"heic" | b"heix" | b"heim" | b"heis" | b"hevc" | b"hevx" | b"mif1" | b"msf1"
+    )
+}
+
+fn be_u16(bytes: &[u8], at: usize) -> Option<u32> {
+    let b = bytes.get(at..at + 2)?;
+    Some(u16::from_be_bytes([b[0], b[1]]) as u32)
+}
+
+fn be_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    let b = bytes.get(at..at + 4)?;
+    Some(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn le_u16(bytes: &[u8], at: usize) -> Option<u32> {
+    let b = bytes.get(at..at + 2)?;
+    Some(u16::from_le_bytes([b[0], b[1]]) as u32)
+}
+
+fn le_u24(bytes: &[u8], at: usize) -> Option<u32> {
+    let b = bytes.get(at..at + 3)?;
+    Some(u32::from_le_bytes([b[0], b[1], b[2], 0]))
+}
+
+fn le_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    let b = bytes.get(at..at + 4)?;
+    Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn jpeg_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    // Some encoders pad the file after EOI with zeros, which is harmless
+    let end = bytes.iter().rposition(|&b| b != 0)? + 1;
+    if end < 4 || bytes[end - 2..end] != [0xFF, 0xD9] {
+        return None;
+    }
+
+    let mut pos = 2;
+    let mut dimensions = None;
+    loop {
+        // Markers may be preceded by any number of 0xFF fill bytes
+        while *bytes.get(pos)? == 0xFF && *bytes.get(pos + 1)? == 0xFF {
+            pos += 1;
+        }
+        if *bytes.get(pos)? != 0xFF {
+            return None;
+        }
+        let marker = *bytes.get(pos + 1)?;
+        let length = be_u16(bytes, pos + 2)? as usize;
+        if length < 2 || pos + 2 + length > end {
+            return None;
+        }
+
+        match marker {
+            // SOFn, excluding DHT (C4), JPG (C8) and DAC (CC)
+            0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                dimensions = Some((be_u16(bytes, pos + 7)?, be_u16(bytes, pos + 5)?));
+            }
+            // Start of scan: entropy coded data runs to EOI
+            0xDA => return dimensions,
+            _ => {}
+        }
+        pos += 2 + length;
+    }
+}
+
+fn png_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    if bytes.get(12..16)? != b"IHDR" {
+        return None;
+    }
+
+    let mut pos = 8;
+    loop {
+        let length = be_u32(bytes, pos)? as usize;
+        let kind = bytes.get(pos + 4..pos + 8)?;
+        // Chunk data plus the trailing CRC must fit
+        let next = pos.checked_add(12)?.checked_add(length)?;
+        if next > bytes.len() {
+            return None;
+        }
+        if kind == b"IEND" {
+            break (next == bytes.len()).then_some((be_u32(bytes, 16)?, be_u32(bytes, 20)?));
+        }
+        pos = next;
+    }
+}
+
+fn webp_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    // The RIFF size covers everything after the 8 byte header
+    let riff_size = le_u32(bytes, 4)? as usize;
+    if riff_size.checked_add(8)? != bytes.len() {
+        return None;
+    }
+
+    let chunk = bytes.get(12..16)?;
+    let data: usize = 20;
+    let chunk_size = le_u32(bytes, 16)? as usize;
+    if data.checked_add(chunk_size)? > bytes.len() {
+        return None;
+    }
+
+    match chunk {
+        b"VP8X" => Some((le_u24(bytes, data + 4)? + 1, le_u24(bytes, data + 7)? + 1)),
+        b"VP8L" => {
+            if *bytes.get(data)? != 0x2F {
+                return None;
+            }
+            let bits = le_u32(bytes, data + 1)?;
+            Some(((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1))
+        }
+        b"VP8 " => {
+            if bytes.get(data + 3..data + 6)? != [0x9D, 0x01, 0x2A] {
+                return None;
+            }
+            Some((le_u16(bytes, data + 6)? & 0x3FFF, le_u16(bytes, data + 8)? & 0x3FFF))
+        }
+        _ => None,
+    }
+}
+
+fn gif_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    if *bytes.last()? != 0x3B {
+        return None;
+    }
+    Some((le_u16(bytes, 6)?, le_u16(bytes, 8)?))
+}
+
+/// Iterates the ISO BMFF boxes in `bytes`, yielding `(type, body)`.
+///
+/// Returns `None` when a box header is malformed or runs past the end.
+fn bmff_boxes(bytes: &[u8]) -> Option<Vec<(&[u8], &[u8])>> {
+    let mut boxes = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let size = be_u32(bytes, pos)? as usize;
+        let kind = bytes.get(pos + 4..pos + 8)?;
+        let (header, size) = match size {
+            0 => (8, bytes.len() - pos),
+            1 => (16, usize::try_from(u64::from_be_bytes(bytes.get(pos + 8..pos + 16)?.try_into().ok()?)).ok()?),
+            n => (8, n),
+        };
+        if size < header || pos.checked_add(size)? > bytes.len() {
+            return None;
+        }
+        boxes.push((kind, &bytes[pos + header..pos + size]));
+        pos += size;
+    }
+    Some(boxes)
+}
+
+fn find_box<'a>(boxes: &[(&[u8], &'a [u8])], kind: &[u8]) -> Option<&'a [u8]> {
+    boxes.iter().find(|(k, _)| *k == kind).map(|(_, body)| *body)
+}
+
+fn heic_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let top = bmff_boxes(bytes)?;
+
+    // meta is a full box: skip its version and flags
+    let meta = find_box(&top, b"meta")?;
+    let meta = bmff_boxes(meta.get(4..)?)?;
+    let iprp = bmff_boxes(find_box(&meta, b"iprp")?)?;
+    let ipco = bmff_boxes(find_box(&iprp, b"ipco")?)?;
+
+    // Thumbnails and tiles carry their own extents; the primary image is the largest
+    ipco.iter()
+        .filter(|(kind, _)| *kind == b"ispe")
+        .filter_map(|(_, body)| Some((be_u32(body, 4)?, be_u32(body, 8)?)))
+        .max_by_key(|&(w, h)| u64::from(w) * u64::from(h))
+}
+
+#[cfg(test)]
+pub(crate) mod fixtures {
+    //! Minimal, structurally valid files for each supported format.
+
+    pub fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = b"\x89PNG\r\n\x1a\n".to_vec();
+        let mut ihdr = Vec::new();
+        ihdr.extend_from_slice(&width.to_be_bytes());
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        // 8-bit RGBA
+        ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+        push_png_chunk(&mut bytes, b"IHDR", &ihdr);
+        push_png_chunk(&mut bytes, b"IDAT", &[0x78, 0x9C, 0x03, 0x00]);
+        push_png_chunk(&mut bytes, b"IEND", &[]);
+        bytes
+    }
+
+    fn push_png_chunk(bytes: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+        bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(kind);
+        bytes.extend_from_slice(data);
+        // CRC is not verified by the parser
+        bytes.extend_from_slice(&[0; 4]);
+    }
+
+    pub fn jpeg(width: u16, height: u16) -> Vec<u8> {
+        let mut bytes = vec![0xFF, 0xD8];
+        // APP0 JFIF
+        bytes.extend_from_slice(&[0xFF, 0xE0, 0x00, 0x10]);
+        bytes.extend_from_slice(b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0");
+        // SOF0, 8-bit, one component
+        bytes.extend_from_slice(&[0xFF, 0xC0, 0x00, 0x0B, 0x08]);
+        bytes.extend_from_slice(&height.to_be_bytes());
+        bytes.extend_from_slice(&width.to_be_bytes());
+        bytes.extend_from_slice(&[0x01, 0x01, 0x11, 0x00]);
+        // SOS followed by a token scan
+        bytes.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3F, 0x00]);
+        bytes.extend_from_slice(&[0x12, 0x34]);
+        bytes.extend_from_slice(&[0xFF, 0xD9]);
+        bytes
+    }
+
+    pub fn webp(width: u32, height: u32) -> Vec<u8> {
+        let bits = (width - 1) | ((height - 1) << 14);
+        let mut chunk = vec![0x2F];
+        chunk.extend_from_slice(&bits.to_le_bytes());
+
+        let mut bytes = b"RIFF".to_vec();
+        // Odd sized chunks are followed by a pad byte
+        bytes.extend_from_slice(&((4 + 8 + chunk.len() + 1) as u32).to_le_bytes());
+        bytes.extend_from_slice(b"WEBPVP8L");
+        bytes.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&chunk);
+        bytes.push(0);
+        bytes
+    }
+
+    pub fn gif(width: u16, height: u16) -> Vec<u8> {
+        let mut bytes = b"GIF89a".to_vec();
+        bytes.extend_from_slice(&width.to_le_bytes());
+        bytes.extend_from_slice(&height.to_le_bytes());
+        bytes.extend_from_slice(&[0x00, 0x00, 0x00, 0x3B]);
+        bytes
+    }
+
+    pub fn heic(width: u32, height: u32) -> Vec<u8> {
+        let mut ispe = vec![0; 4];
+        ispe.extend_from_slice(&width.to_be_bytes());
+        ispe.extend_from_slice(&height.to_be_bytes());
+
+        let ipco = bmff_box(b"ipco", &bmff_box(b"ispe", &ispe));
+        let iprp = bmff_box(b"iprp", &ipco);
+        let mut meta = vec![0; 4];
+        meta.extend_from_slice(&iprp);
+
+        let mut bytes = bmff_box(b"ftyp", b"heic\0\0\0\0mif1heic");
+        bytes.extend_from_slice(&bmff_box(b"meta", &meta));
+        bytes.extend_from_slice(&bmff_box(b"mdat", &[0; 8]));
+        bytes
+    }
+
+    /// HEIC whose `Exif` item holds `tiff`, with the file range of that item.
+    pub fn heic_with_exif(tiff: &[u8]) -> (Vec<u8>, std::ops::Range<usize>) {
+        let mut exif = 6u32.to_be_bytes().to_vec();
+        exif.extend_from_slice(b"Exif\0\0");
+        exif.extend_from_slice(tiff);
+
+        let meta = |exif_offset: u32| {
+            let mut infe = vec![2, 0, 0, 0, 0, 1, 0, 0];
+            infe.extend_from_slice(b"Exif\0");
+            let mut iinf = vec![0, 0, 0, 0, 0, 1];
+            iinf.extend_from_slice(&bmff_box(b"infe", &infe));
+
+            // Version 0, 4 byte offsets and lengths, no base offset
+            let mut iloc = vec![0, 0, 0, 0, 0x44, 0x00, 0, 1, 0, 1, 0, 0, 0, 1];
+            iloc.extend_from_slice(&exif_offset.to_be_bytes());
+            iloc.extend_from_slice(&(exif.len() as u32).to_be_bytes());
+
+            let mut ispe = vec![0; 4];
+            ispe.extend_from_slice(&64u32.to_be_bytes());
+            ispe.extend_from_slice(&48u32.to_be_bytes());
+            let iprp = bmff_box(b"iprp", &bmff_box(b"ipco", &bmff_box(b"ispe", &ispe)));
+
+            let mut body = vec![0; 4];
+            body.extend_from_slice(&bmff_box(b"iinf", &iinf));
+            body.extend_from_slice(&bmff_box(b"iloc", &iloc));
+            body.extend_from_slice(&iprp);
+            bmff_box(b"meta", &body)
+        };
+
+        let mut bytes = bmff_box(b"ftyp", b"heic\0\0\0\0mif1heic");
+        let offset = bytes.len() + meta(0).len() + 8;
+        bytes.extend_from_slice(&meta(offset as u32));
+        bytes.extend_from_slice(&bmff_box(b"mdat", &exif));
+        (bytes, offset..offset + exif.len())
+    }
+
+    fn bmff_box(kind: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut bytes = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(kind);
+        bytes.extend_from_slice(body);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inspect_supported_formats() {
+        let cases = [
+            (fixtures::jpeg(640, 480), ImageFormat::Jpeg, (640, 480)),
+            (fixtures::png(32, 16), ImageFormat::Png, (32, 16)),
+            (fixtures::webp(300, 200), ImageFormat::WebP, (300, 200)),
+            (fixtures::gif(10, 20), ImageFormat::Gif, (10, 20)),
+            (fixtures::heic(4032, 3024), ImageFormat::Heic, (4032, 3024)),
+        ];
+
+        for (bytes, format, (width, height)) in cases {
+            let image = DecodedImage::inspect(bytes).unwrap();
+            assert_eq!(image.format, format);
+            assert_eq!((image.width, image.height), (width, height), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn test_inspect_rejects_truncated_files() {
+        for bytes in [
+            fixtures::jpeg(640, 480),
+            fixtures::png(32, 16),
+            fixtures::webp(300, 200),
+            fixtures::gif(10, 20),
+            fixtures::heic(4032, 3024),
+        ] {
+            let truncated = bytes[..bytes.len() - 3].to_vec();
+            assert!(matches!(DecodedImage::inspect(truncated), Err(AppError::InvalidImageFormat(_))));
+        }
+    }
+
+    #[test]
+    fn test_inspect_rejects_appended_payloads() {
+        let mut png = fixtures::png(32, 16);
+        png.extend_from_slice(b"PK\x03\x04polyglot");
+        assert!(matches!(DecodedImage::inspect(png), Err(AppError::InvalidImageFormat(_))));
+
+        let mut jpeg = fixtures::jpeg(640, 480);
+        jpeg.extend_from_slice(b"<script>alert(1)</script>");
+        assert!(matches!(DecodedImage::inspect(jpeg), Err(AppError::InvalidImageFormat(_))));
+
+        // Zero padding after EOI is tolerated
+        let mut padded = fixtures::jpeg(640, 480);
+        padded.extend_from_slice(&[0; 16]);
+        assert!(DecodedImage::inspect(padded).is_ok());
+    }
+
+    #[test]
+    fn test_inspect_rejects_oversized_webp_chunk() {
+        // A chunk size that would wrap the offset on 32-bit wasm
+        let mut webp = fixtures::webp(300, 200);
+        webp[16..20].copy_from_slice(&0xFFFF_FFFFu32.to_le_bytes());
+        assert!(webp_dimensions(&webp).is_none());
+        assert!(matches!(DecodedImage::inspect(webp), Err(AppError::InvalidImageFormat(_))));
+    }
+
+    #[test]
+    fn test_inspect_rejects_unknown_and_empty() {
+        assert!(matches!(DecodedImage::inspect(b"BM\x00\x00".to_vec()), Err(AppError::UnsupportedImageType(_))));
+        assert!(matches!(DecodedImage::inspect(fixtures::png(0, 16)), Err(AppError::InvalidImageFormat(_))));
+    }
+
+    #[test]
+    fn test_from_mime_type_aliases() {
+        assert_eq!(ImageFormat::from_mime_type("image/jpg"), Some(ImageFormat::Jpeg));
+        assert_eq!(ImageFormat::from_mime_type("IMAGE/PNG"), Some(ImageFormat::Png));
+        assert_eq!(ImageFormat::from_mime_type("image/heif"), Some(ImageFormat::Heic));
+        assert_eq!(ImageFormat::from_mime_type("image/bmp"), None);
+    }
+}
@@ -0,0 +1,216 @@
+use std::io::Cursor;
+
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageReader, Limits};
+use worker::Env;
+
+use super::encode::{codec_format, encode, DEFAULT_JPEG_QUALITY};
+use super::{DecodedImage, ImageFormat, MAX_IMAGE_PIXELS};
+use crate::error::{AppError, AppResult};
+
+const DEFAULT_MAX_EDGE: u32 = 1536;
+
+/// How uploads are normalised before they are sent to a provider.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreprocessOptions {
+    /// Longest edge, in pixels, an image is downscaled to.
+    pub max_edge: u32,
+}
+
+impl Default for PreprocessOptions {
+    fn default() -> Self {
+        Self { max_edge: DEFAULT_MAX_EDGE }
+    }
+}
+
+impl PreprocessOptions {
+    /// Reads `IMAGE_MAX_EDGE` from the environment, using the default when unset.
+    pub fn from_env(env: &Env) -> AppResult<Self> {
+        Self::from_var(env.var("IMAGE_MAX_EDGE").ok().map(|v| v.to_string()).as_deref())
+    }
+
+    fn from_var(max_edge: Option<&str>) -> AppResult<Self> {
+        let Some(value) = max_edge else {
+            return Ok(Self::default());
+        };
+
+        match value.trim().parse() {
+            Ok(max_edge) if max_edge > 0 => Ok(Self { max_edge }),
+            _ => Err(AppError::InternalError(format!(
+                "IMAGE_MAX_EDGE must be a positive integer, got '{}'",
+                value
+            ))),
+        }
+    }
+}
+
+/// Normalises an upload for the provider.
+///
+/// Applies the EXIF orientation, downscales so neither edge exceeds
+/// `max_edge`, and re-encodes as JPEG, or PNG when the image has an alpha
+/// channel. Re-encoding drops EXIF and every other metadata block.
+///
+/// HEIC has no pure-Rust decoder, so it is passed through untouched; the
+/// provider accepts it directly.
+pub fn preprocess(image: DecodedImage, options: &PreprocessOptions) -> AppResult<DecodedImage> {
+    let Some(codec) = codec_format(image.format) else {
+        return Ok(image);
+    };
+
+    let decode_error = |e: image::ImageError| match e {
+        image::ImageError::Limits(e) => AppError::ImageTooLarge(format!(
+            "The {} image is too large to process: {}",
+            image.format.name(),
+            e
+        )),
+        e => AppError::InvalidImageFormat(format!("Could not decode the {} image: {}", image.format.name(), e)),
+    };
+
+    // The whole image is decoded before it is downscaled, so the canvas has to
+    // fit in the isolate's memory. The decoder's own dimensions are checked too,
+    // in case they disagree with the ones read while inspecting the upload
+    let mut limits = Limits::default();
+    limits.max_alloc = Some(MAX_IMAGE_PIXELS * 4);
+    let mut reader = ImageReader::with_format(Cursor::new(&image.bytes), codec);
+    reader.limits(limits);
+    let mut decoder = reader.into_decoder().map_err(decode_error)?;
+    let (width, height) = decoder.dimensions();
+    if u64::from(width) * u64::from(height) > MAX_IMAGE_PIXELS {
+        return Err(AppError::ImageTooLarge(format!(
+            "Image dimensions {}x{} exceed the {} megapixel limit",
+            width,
+            height,
+            MAX_IMAGE_PIXELS / 1_000_000
+        )));
+    }
+    let orientation = decoder.orientation().map_err(decode_error)?;
+    let mut pixels = DynamicImage::from_decoder(decoder).map_err(decode_error)?;
+    pixels.apply_orientation(orientation);
+
+    if pixels.width().max(pixels.height()) > options.max_edge {
+        // resize() keeps the aspect ratio within the bounding box
+        pixels = pixels.resize(options.max_edge, options.max_edge, FilterType::Triangle);
+    }
+
+    let format = if pixels.color().has_alpha() { ImageFormat::Png } else { ImageFormat::Jpeg };
+    let bytes = encode(&pixels, format, DEFAULT_JPEG_QUALITY)?;
+
+    Ok(DecodedImage {
+        bytes,
+        format,
+        width: pixels.width(),
+        height: pixels.height(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imaging::fixtures;
+    use image::{ImageFormat as CodecFormat, Rgb, RgbImage, Rgba, RgbaImage};
+
+    fn encode(image: DynamicImage, format: CodecFormat) -> DecodedImage {
+        let mut bytes = Vec::new();
+        image.write_to(&mut Cursor::new(&mut bytes), format).unwrap();
+        DecodedImage::inspect(bytes).unwrap()
+    }
+
+    fn photo(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(width, height, Rgb([200, 120, 40])))
+    }
+
+    /// Inserts an APP1 Exif segment carrying only an orientation tag after SOI.
+    fn with_exif_orientation(jpeg: &[u8], orientation: u16) -> Vec<u8> {
+        let mut tiff = b"MM\0*\0\0\0\x08\0\x01".to_vec();
+        tiff.extend_from_slice(&[0x01, 0x12, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01]);
+        tiff.extend_from_slice(&orientation.to_be_bytes());
+        tiff.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend_from_slice(&tiff);
+
+        let mut bytes = jpeg[..2].to_vec();
+        bytes.extend_from_slice(&[0xFF, 0xE1]);
+        bytes.extend_from_slice(&((app1.len() + 2) as u16).to_be_bytes());
+        bytes.extend_from_slice(&app1);
+        bytes.extend_from_slice(&jpeg[2..]);
+        bytes
+    }
+
+    #[test]
+    fn test_options_from_var() {
+        assert_eq!(PreprocessOptions::from_var(None).unwrap().max_edge, DEFAULT_MAX_EDGE);
+        assert_eq!(PreprocessOptions::from_var(Some("1024")).unwrap().max_edge, 1024);
+        assert!(PreprocessOptions::from_var(Some("0")).is_err());
+        assert!(PreprocessOptions::from_var(Some("big")).is_err());
+    }
+
+    #[test]
+    fn test_downscales_to_max_edge() {
+        let image = encode(photo(400, 200), CodecFormat::Png);
+        let result = preprocess(image, &PreprocessOptions { max_edge: 100 }).unwrap();
+        assert_eq!((result.width, result.height), (100, 50));
+        assert_eq!(result.format, ImageFormat::Jpeg);
+
+        let reparsed = DecodedImage::inspect(result.bytes).unwrap();
+        assert_eq!((reparsed.width, reparsed.height), (100, 50));
+    }
+
+    #[test]
+    fn test_small_images_keep_their_size() {
+        let image = encode(photo(40, 30), CodecFormat::WebP);
+        let result = preprocess(image, &PreprocessOptions::default()).unwrap();
+        assert_eq!((result.width, result.height), (40, 30));
+        assert_eq!(result.format, ImageFormat::Jpeg);
+    }
+
+    #[test]
+    fn test_alpha_is_preserved_as_png() {
+        let transparent = DynamicImage::ImageRgba8(RgbaImage::from_pixel(20, 10, Rgba([0, 0, 0, 0])));
+        let result = preprocess(encode(transparent, CodecFormat::Png), &PreprocessOptions::default()).unwrap();
+        assert_eq!(result.format, ImageFormat::Png);
+    }
+
+    #[test]
+    fn test_applies_exif_orientation_and_strips_metadata() {
+        let jpeg = encode(photo(40, 20), CodecFormat::Jpeg);
+        // 6: rotate 90 degrees clockwise
+        let rotated = DecodedImage::inspect(with_exif_orientation(&jpeg.bytes, 6)).unwrap();
+        assert_eq!((rotated.width, rotated.height), (40, 20));
+
+        let result = preprocess(rotated, &PreprocessOptions::default()).unwrap();
+        assert_eq!((result.width, result.height), (20, 40));
+        assert!(!result.bytes.windows(6).any(|w| w == b"Exif\0\0"));
+    }
+
+    #[test]
+    fn test_heic_passes_through() {
+        let heic = DecodedImage::inspect(fixtures::heic(4032, 3024)).unwrap();
+        let result = preprocess(heic.clone(), &PreprocessOptions { max_edge: 100 }).unwrap();
+        assert_eq!(result.bytes, heic.bytes);
+        assert_eq!(result.format, ImageFormat::Heic);
+    }
+
+    #[test]
+    fn test_decode_is_bounded() {
+        // A real JPEG whose frame header claims a canvas too big to decode in an isolate
+        let mut bytes = encode(photo(8, 8), CodecFormat::Jpeg).bytes;
+        let sof = bytes.windows(2).position(|marker| marker == [0xFF, 0xC0]).unwrap();
+        bytes[sof + 5..sof + 9].copy_from_slice(&[0x4E, 0x20, 0x4E, 0x20]); // 20000x20000
+        let jpeg = DecodedImage::inspect(bytes).unwrap();
+        assert!(matches!(
+            preprocess(jpeg, &PreprocessOptions::default()),
+            Err(AppError::ImageTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn test_undecodable_image_is_rejected() {
+        // Structurally valid container with no real pixel data
+        let png = DecodedImage::inspect(fixtures::png(32, 16)).unwrap();
+        assert!(matches!(
+            preprocess(png, &PreprocessOptions::default()),
+            Err(AppError::InvalidImageFormat(_))
+        ));
+    }
+}
@@ -0,0 +1,451 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use super::{be_u16, be_u32, bmff_boxes, find_box, le_u32, DecodedImage, ImageFormat};
+
+/// Kinds of embedded metadata that can identify a person, place or device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataKind {
+    Exif,
+    /// GPS coordinates inside an Exif block.
+    Gps,
+    Xmp,
+    Iptc,
+    Comment,
+    Timestamp,
+}
+
+impl MetadataKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MetadataKind::Exif => "exif",
+            MetadataKind::Gps => "gps",
+            MetadataKind::Xmp => "xmp",
+            MetadataKind::Iptc => "iptc",
+            MetadataKind::Comment => "comment",
+            MetadataKind::Timestamp => "timestamp",
+        }
+    }
+}
+
+/// A metadata block found in an image, with what it contains.
+struct Block {
+    range: Range<usize>,
+    kinds: Vec<MetadataKind>,
+}
+
+/// Lists the privacy relevant metadata embedded in `image`, without changing it.
+pub fn find_metadata(image: &DecodedImage) -> Vec<MetadataKind> {
+    collect_kinds(&metadata_blocks(image))
+}
+
+/// Removes embedded metadata from `image`, returning what was removed.
+///
+/// Removal is lossless: pixel data is never re-encoded. JPEG, PNG and WebP
+/// blocks are cut out, while HEIC items are zeroed in place so the item
+/// location table stays valid. ICC colour profiles are kept.
+pub fn scrub(mut image: DecodedImage) -> (DecodedImage, Vec<MetadataKind>) {
+    let blocks = metadata_blocks(&image);
+    if blocks.is_empty() {
+        return (image, Vec::new());
+    }
+    let kinds = collect_kinds(&blocks);
+
+    if image.format == ImageFormat::Heic {
+        for block in &blocks {
+            image.bytes[block.range.clone()].fill(0);
+        }
+        return (image, kinds);
+    }
+
+    let mut bytes = Vec::with_capacity(image.bytes.len());
+    let mut pos = 0;
+    for block in &blocks {
+        bytes.extend_from_slice(&image.bytes[pos..block.range.start]);
+        pos = block.range.end;
+    }
+    bytes.extend_from_slice(&image.bytes[pos..]);
+
+    if image.format == ImageFormat::WebP {
+        fix_webp_header(&mut bytes);
+    }
+
+    image.bytes = bytes;
+    (image, kinds)
+}
+
+fn collect_kinds(blocks: &[Block]) -> Vec<MetadataKind> {
+    let mut kinds: Vec<MetadataKind> = blocks.iter().flat_map(|b| b.kinds.iter().copied()).collect();
+    kinds.sort();
+    kinds.dedup();
+    kinds
+}
+
+/// Finds metadata blocks, sorted and non-overlapping. GIF is not inspected:
+/// uploads in that format are rejected and providers never return it.
+fn metadata_blocks(image: &DecodedImage) -> Vec<Block> {
+    let bytes = &image.bytes;
+    let mut blocks = match image.format {
+        ImageFormat::Jpeg => jpeg_blocks(bytes),
+        ImageFormat::Png => png_blocks(bytes),
+        ImageFormat::WebP => webp_blocks(bytes),
+        ImageFormat::Heic => heic_blocks(bytes),
+        ImageFormat::Gif => None,
+    }
+    .unwrap_or_default();
+
+    blocks.sort_by_key(|b| b.range.start);
+    blocks.dedup_by(|later, earlier| later.range.start < earlier.range.end);
+    blocks
+}
+
+/// Kinds for an Exif payload: always `Exif`, plus `Gps` when IFD0 links a GPS IFD.
+fn exif_kinds(tiff: &[u8]) -> Vec<MetadataKind> {
+    let tiff = tiff.strip_prefix(b"Exif\0\0").unwrap_or(tiff);
+    let mut kinds = vec![MetadataKind::Exif];
+    if exif_has_gps(tiff) == Some(true) {
+        kinds.push(MetadataKind::Gps);
+    }
+    kinds
+}
+
+fn exif_has_gps(tiff: &[u8]) -> Option<bool> {
+    const GPS_IFD_POINTER: u32 = 0x8825;
+
+    let little_endian = match tiff.get(0..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    let u16_at = |at| if little_endian { super::le_u16(tiff, at) } else { be_u16(tiff, at) };
+    let u32_at = |at| if little_endian { le_u32(tiff, at) } else { be_u32(tiff, at) };
+
+    let ifd = u32_at(4)? as usize;
+    let count = u16_at(ifd)? as usize;
+    Some((0..count).any(|i| u16_at(ifd + 2 + i * 12) == Some(GPS_IFD_POINTER)))
+}
+
+fn jpeg_blocks(bytes: &[u8]) -> Option<Vec<Block>> {
+    let mut blocks = Vec::new();
+    let mut pos = 2;
+    loop {
+        while *bytes.get(pos)? == 0xFF && *bytes.get(pos + 1)? == 0xFF {
+            pos += 1;
+        }
+        let marker = *bytes.get(pos + 1)?;
+        if marker == 0xDA {
+            return Some(blocks);
+        }
+        let length = be_u16(bytes, pos + 2)? as usize;
+        let end = pos + 2 + length;
+        let payload = bytes.get(pos + 4..end)?;
+
+        let kinds = match marker {
+            0xE1 if payload.starts_with(b"Exif\0\0") => exif_kinds(payload),
+            0xE1 if payload.starts_with(b"http://ns.adobe.com/") => vec![MetadataKind::Xmp],
+            0xED => vec![MetadataKind::Iptc],
+            0xFE => vec![MetadataKind::Comment],
+            _ => Vec::new(),
+        };
+        if !kinds.is_empty() {
+            blocks.push(Block { range: pos..end, kinds });
+        }
+        pos = end;
+    }
+}
+
+fn png_blocks(bytes: &[u8]) -> Option<Vec<Block>> {
+    let mut blocks = Vec::new();
+    let mut pos = 8;
+    while pos < bytes.len() {
+        let length = be_u32(bytes, pos)? as usize;
+        let kind = bytes.get(pos + 4..pos + 8)?;
+        let end = pos + 12 + length;
+        let data = bytes.get(pos + 8..pos + 8 + length)?;
+
+        let kinds = match kind {
+            b"eXIf" => exif_kinds(data),
+            b"tEXt" | b"zTXt" | b"iTXt" if data.starts_with(b"XML:com.adobe.xmp\0") => vec![MetadataKind::Xmp],
+            b"tEXt" | b"zTXt" | b"iTXt" => vec![MetadataKind::Comment],
+            b"tIME" => vec![MetadataKind::Timestamp],
+            _ => Vec::new(),
+        };
+        if !kinds.is_empty() {
+            blocks.push(Block { range: pos..end, kinds });
+        }
+        pos = end;
+    }
+    Some(blocks)
+}
+
+// VP8X feature flags for the chunks that can be removed
+const WEBP_EXIF_FLAG: u8 = 0x08;
+const WEBP_XMP_FLAG: u8 = 0x04;
+
+fn webp_blocks(bytes: &[u8]) -> Option<Vec<Block>> {
+    let mut blocks = Vec::new();
+    let mut pos = 12;
+    while pos < bytes.len() {
+        let kind = bytes.get(pos..pos + 4)?;
+        let size = le_u32(bytes, pos + 4)? as usize;
+        // Chunks are padded to an even length
+        let end = (pos + 8 + size + (size & 1)).min(bytes.len());
+        let data = bytes.get(pos + 8..pos + 8 + size)?;
+
+        let kinds = match kind {
+            b"EXIF" => exif_kinds(data),
+            b"XMP " => vec![MetadataKind::Xmp],
+            _ => Vec::new(),
+        };
+        if !kinds.is_empty() {
+            blocks.push(Block { range: pos..end, kinds });
+        }
+        pos = end;
+    }
+    Some(blocks)
+}
+
+/// Rewrites the RIFF size and VP8X flags after chunks were cut out.
+fn fix_webp_header(bytes: &mut [u8]) {
+    let riff_size = (bytes.len() - 8) as u32;
+    bytes[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    if bytes.get(12..16) == Some(b"VP8X") && bytes.len() > 20 {
+        bytes[20] &= !(WEBP_EXIF_FLAG | WEBP_XMP_FLAG);
+    }
+}
+
+fn heic_blocks(bytes: &[u8]) -> Option<Vec<Block>> {
+    let top = bmff_boxes(bytes)?;
+    let meta = find_box(&top, b"meta")?;
+    let meta = bmff_boxes(meta.get(4..)?)?;
+
+    let items = heic_metadata_items(find_box(&meta, b"iinf")?)?;
+    if items.is_empty() {
+        return Some(Vec::new());
+    }
+    let locations = heic_item_locations(find_box(&meta, b"iloc")?)?;
+
+    let mut blocks = Vec::new();
+    for (id, kind) in items {
+        for range in locations.iter().filter(|(item, _)| *item == id).map(|(_, r)| r.clone()) {
+            let data = bytes.get(range.clone())?;
+            // Already scrubbed
+            if data.iter().all(|&b| b == 0) {
+                continue;
+            }
+            let kinds = match kind {
+                // Exif items start with the offset of the TIFF header
+                MetadataKind::Exif => exif_kinds(data.get(4 + be_u32(data, 0)? as usize..).unwrap_or_default()),
+                kind => vec![kind],
+            };
+            blocks.push(Block { range, kinds });
+        }
+    }
+    Some(blocks)
+}
+
+/// Item IDs of the `Exif` and XMP items declared in an `iinf` box.
+fn heic_metadata_items(iinf: &[u8]) -> Option<Vec<(u32, MetadataKind)>> {
+    let version = *iinf.first()?;
+    let entries = if version == 0 { iinf.get(6..)? } else { iinf.get(8..)? };
+
+    let mut items = Vec::new();
+    for (kind, infe) in bmff_boxes(entries)? {
+        if kind != b"infe" {
+            continue;
+        }
+        let (id, rest) = match *infe.first()? {
+            2 => (be_u16(infe, 4)?, infe.get(8..)?),
+            3 => (be_u32(infe, 4)?, infe.get(10..)?),
+            _ => continue,
+        };
+        match rest.get(0..4)? {
+            b"Exif" => items.push((id, MetadataKind::Exif)),
+            b"mime" if rest.windows(19).any(|w| w == b"application/rdf+xml") => {
+                items.push((id, MetadataKind::Xmp))
+            }
+            _ => {}
+        }
+    }
+    Some(items)
+}
+
+/// File ranges of every item stored in the file itself (construction method 0).
+fn heic_item_locations(iloc: &[u8]) -> Option<Vec<(u32, Range<usize>)>> {
+    let version = *iloc.first()?;
+    let offset_size = (iloc.get(4)? >> 4) as usize;
+    let length_size = (iloc.get(4)? & 0x0F) as usize;
+    let base_offset_size = (iloc.get(5)? >> 4) as usize;
+    let index_size = if version > 0 { (iloc.get(5)? & 0x0F) as usize } else { 0 };
+
+    let read = |pos: &mut usize, size: usize| -> Option<u64> {
+        let value = iloc
+            .get(*pos..*pos + size)?
+            .iter()
+            .fold(0u64, |acc, &b| (acc << 8) | u64::from(b));
+        *pos += size;
+        Some(value)
+    };
+
+    let mut pos = 6;
+    let item_count = read(&mut pos, if version < 2 { 2 } else { 4 })?;
+    let mut locations = Vec::new();
+    for _ in 0..item_count {
+        let id = read(&mut pos, if version < 2 { 2 } else { 4 })? as u32;
+        let construction_method = if version > 0 { read(&mut pos, 2)? & 0x0F } else { 0 };
+        let _data_reference_index = read(&mut pos, 2)?;
+        let base_offset = read(&mut pos, base_offset_size)?;
+        let extent_count = read(&mut pos, 2)?;
+        for _ in 0..extent_count {
+            read(&mut pos, index_size)?;
+            let offset = base_offset.checked_add(read(&mut pos, offset_size)?)? as usize;
+            let length = read(&mut pos, length_size)? as usize;
+            if construction_method == 0 {
+                locations.push((id, offset..offset.checked_add(length)?));
+            }
+        }
+    }
+    Some(locations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imaging::fixtures;
+
+    // Big-endian TIFF with an orientation tag and, optionally, a GPS IFD pointer
+    fn tiff(with_gps: bool) -> Vec<u8> {
+        let mut tiff = b"MM\0*\0\0\0\x08".to_vec();
+        let entries: u16 = if with_gps { 2 } else { 1 };
+        tiff.extend_from_slice(&entries.to_be_bytes());
+        tiff.extend_from_slice(&[0x01, 0x12, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00]);
+        if with_gps {
+            tiff.extend_from_slice(&[0x88, 0x25, 0x00, 0x04, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00]);
+        }
+        tiff.extend_from_slice(&[0, 0, 0, 0]);
+        tiff
+    }
+
+    fn jpeg_segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+        let mut segment = vec![0xFF, marker];
+        segment.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+        segment.extend_from_slice(payload);
+        segment
+    }
+
+    fn png_chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(kind);
+        chunk.extend_from_slice(data);
+        chunk.extend_from_slice(&[0; 4]);
+        chunk
+    }
+
+    fn decoded(bytes: Vec<u8>) -> DecodedImage {
+        DecodedImage::inspect(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_jpeg_exif_gps_and_comments_are_cut() {
+        let clean = fixtures::jpeg(64, 48);
+        let mut exif = b"Exif\0\0".to_vec();
+        exif.extend_from_slice(&tiff(true));
+
+        let mut bytes = clean[..2].to_vec();
+        bytes.extend_from_slice(&jpeg_segment(0xE1, &exif));
+        bytes.extend_from_slice(&jpeg_segment(0xE1, b"http://ns.adobe.com/xap/1.0/\0<x:xmpmeta/>"));
+        bytes.extend_from_slice(&jpeg_segment(0xFE, b"shot on my phone"));
+        bytes.extend_from_slice(&clean[2..]);
+
+        let image = decoded(bytes);
+        let found = find_metadata(&image);
+        assert_eq!(found, vec![MetadataKind::Exif, MetadataKind::Gps, MetadataKind::Xmp, MetadataKind::Comment]);
+
+        let (scrubbed, removed) = scrub(image);
+        assert_eq!(removed, found);
+        assert_eq!(scrubbed.bytes, clean);
+        assert!(find_metadata(&scrubbed).is_empty());
+    }
+
+    #[test]
+    fn test_jpeg_exif_without_gps() {
+        let clean = fixtures::jpeg(64, 48);
+        let mut exif = b"Exif\0\0".to_vec();
+        exif.extend_from_slice(&tiff(false));
+        let mut bytes = clean[..2].to_vec();
+        bytes.extend_from_slice(&jpeg_segment(0xE1, &exif));
+        bytes.extend_from_slice(&clean[2..]);
+
+        assert_eq!(find_metadata(&decoded(bytes)), vec![MetadataKind::Exif]);
+    }
+
+    #[test]
+    fn test_png_text_exif_and_time_are_cut() {
+        let clean = fixtures::png(4, 4);
+        // Insert after IHDR (8 byte signature + 25 byte chunk)
+        let mut bytes = clean[..33].to_vec();
+        bytes.extend_from_slice(&png_chunk(b"eXIf", &tiff(true)));
+        bytes.extend_from_slice(&png_chunk(b"tEXt", b"Author\0someone"));
+        bytes.extend_from_slice(&png_chunk(b"iTXt", b"XML:com.adobe.xmp\0\0\0\0\0<x/>"));
+        bytes.extend_from_slice(&png_chunk(b"tIME", &[0x07, 0xE9, 1, 1, 0, 0, 0]));
+        bytes.extend_from_slice(&clean[33..]);
+
+        let (scrubbed, removed) = scrub(decoded(bytes));
+        assert_eq!(
+            removed,
+            vec![MetadataKind::Exif, MetadataKind::Gps, MetadataKind::Xmp, MetadataKind::Comment, MetadataKind::Timestamp]
+        );
+        assert_eq!(scrubbed.bytes, clean);
+    }
+
+    #[test]
+    fn test_webp_chunks_are_cut_and_header_fixed() {
+        let lossless = fixtures::webp(8, 8);
+        let mut vp8x = vec![WEBP_EXIF_FLAG | WEBP_XMP_FLAG, 0, 0, 0, 7, 0, 0, 7, 0, 0];
+        let mut body = b"VP8X".to_vec();
+        body.extend_from_slice(&(vp8x.len() as u32).to_le_bytes());
+        body.append(&mut vp8x);
+        body.extend_from_slice(&lossless[12..]);
+        let clean_len = body.len();
+        body.extend_from_slice(b"EXIF");
+        let exif = tiff(false);
+        body.extend_from_slice(&(exif.len() as u32).to_le_bytes());
+        body.extend_from_slice(&exif);
+        body.extend_from_slice(b"XMP \x03\0\0\0<x/\0");
+
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend_from_slice(&((body.len() + 4) as u32).to_le_bytes());
+        bytes.extend_from_slice(b"WEBP");
+        bytes.extend_from_slice(&body);
+
+        let (scrubbed, removed) = scrub(decoded(bytes));
+        assert_eq!(removed, vec![MetadataKind::Exif, MetadataKind::Xmp]);
+        assert_eq!(scrubbed.bytes.len(), 12 + clean_len);
+        assert_eq!(scrubbed.bytes[20] & (WEBP_EXIF_FLAG | WEBP_XMP_FLAG), 0);
+        // Still a well-formed file
+        assert_eq!(decoded(scrubbed.bytes).width, 8);
+    }
+
+    #[test]
+    fn test_heic_exif_item_is_zeroed() {
+        let (bytes, exif_range) = fixtures::heic_with_exif(&tiff(true));
+        let (scrubbed, removed) = scrub(decoded(bytes.clone()));
+
+        assert_eq!(removed, vec![MetadataKind::Exif, MetadataKind::Gps]);
+        assert_eq!(scrubbed.bytes.len(), bytes.len());
+        assert!(scrubbed.bytes[exif_range.clone()].iter().all(|&b| b == 0));
+        assert_eq!(scrubbed.bytes[..exif_range.start], bytes[..exif_range.start]);
+        assert!(find_metadata(&decoded(scrubbed.bytes)).is_empty());
+    }
+
+    #[test]
+    fn test_clean_images_are_untouched() {
+        let png = decoded(fixtures::png(4, 4));
+        let (scrubbed, removed) = scrub(png.clone());
+        assert!(removed.is_empty());
+        assert_eq!(scrubbed.bytes, png.bytes);
+        assert!(find_metadata(&decoded(fixtures::heic(4, 4))).is_empty());
+    }
+}
@@ -1,16 +1,24 @@
 use worker::*;
 
 mod models;
+mod auth;
+mod emoji;
 mod error;
 mod handlers;
+mod imaging;
+mod negotiate;
+mod prompt;
 mod providers;
+mod rate_limit;
+mod upload;
 
-use handlers::handle_transform;
+use handlers::{handle_animate, handle_emojis, handle_quota, handle_transform};
 
 fn add_cors_headers(mut response: Response) -> Result<Response> {
     response.headers_mut().set("Access-Control-Allow-Origin", "*")?;
     response.headers_mut().set("Access-Control-Allow-Methods", "GET, POST, OPTIONS")?;
-    response.headers_mut().set("Access-Control-Allow-Headers", "Content-Type")?;
+    response.headers_mut().set("Access-Control-Allow-Headers", "Content-Type, Authorization")?;
+    response.headers_mut().set("Access-Control-Expose-Headers", "X-RateLimit-Limit, X-RateLimit-Remaining, X-RateLimit-Reset, Retry-After, X-Request-Id, X-Processing-Time-Ms, X-Model-Version, X-Intensity, X-Prompt-Version, X-Subject, X-Frames, X-Composite, X-Composite-Badge, X-Metadata-Removed")?;
     response.headers_mut().set("Access-Control-Max-Age", "86400")?;
     Ok(response)
 }
@@ -53,6 +61,9 @@ async fn main(req: Request, env: Env, _ctx: Context) -> Result<Response> {
                 })
         })
         .post_async("/api/transform", handle_transform)
+        .post_async("/api/animate", handle_animate)
+        .get_async("/api/quota", handle_quota)
+        .get("/api/emojis", handle_emojis)
         .get("/", |_, _| {
             Response::ok(include_str!("../index.html"))
                 .map(|mut r| {
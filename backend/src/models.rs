@@ -1,22 +1,257 @@
 use serde::{Deserialize, Serialize};
+use crate::emoji::catalog::CatalogEmoji;
+use crate::imaging::animate::AnimationFormat;
+use crate::imaging::composite::CompositeLayout;
+use crate::imaging::encode::OutputFormat;
+use crate::imaging::scrub::MetadataKind;
+use crate::prompt::subject::Subject;
+use crate::prompt::Intensity;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The uploaded image: a base64 data URL in JSON bodies, or the bytes of a
+/// multipart or raw upload, kept as they are rather than encoded into one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum ImageUpload {
+    DataUrl(String),
+    Bytes { mime_type: String, bytes: Vec<u8> },
+}
+
+impl ImageUpload {
+    pub fn is_empty(&self) -> bool {
+        match self {
+            ImageUpload::DataUrl(data_url) => data_url.is_empty(),
+            ImageUpload::Bytes { bytes, .. } => bytes.is_empty(),
+        }
+    }
+}
+
+impl Default for ImageUpload {
+    fn default() -> Self {
+        ImageUpload::DataUrl(String::new())
+    }
+}
+
+impl From<String> for ImageUpload {
+    fn from(data_url: String) -> Self {
+        ImageUpload::DataUrl(data_url)
+    }
+}
+
+impl From<ImageUpload> for String {
+    fn from(image: ImageUpload) -> Self {
+        match image {
+            ImageUpload::DataUrl(data_url) => data_url,
+            ImageUpload::Bytes { mime_type, bytes } => crate::upload::to_data_url(&mime_type, &bytes),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TransformRequest {
-    pub image: String,
+    pub image: ImageUpload,
+    /// Emoji or blend to match; may be left empty when `description` is given.
+    #[serde(default)]
+    pub emoji: String,
+    /// Free-text expression, used alongside or instead of the emoji.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Format to return the transformed image in; the provider's format when absent.
+    #[serde(default)]
+    pub output_format: Option<OutputFormat>,
+    /// JPEG quality (1-100) for `jpeg` output.
+    #[serde(default)]
+    pub quality: Option<u8>,
+    /// Longest edge of the returned image, which is only ever downscaled.
+    #[serde(default)]
+    pub max_dimension: Option<u32>,
+    /// Number of variations to generate; one when absent.
+    #[serde(default)]
+    pub n: Option<u32>,
+    /// How strongly to apply the expression: `subtle`, `moderate` or `extreme`, or 0.0-1.0.
+    #[serde(default)]
+    pub intensity: Option<Intensity>,
+    /// Which subject to edit when the photo has several faces: an index
+    /// counted from the left, or a bounding box. Every face is fair game when absent.
+    #[serde(default)]
+    pub subject: Option<Subject>,
+    /// A different emoji for each of several subjects, used instead of `emoji`
+    /// and `subject`. Each is edited in turn.
+    #[serde(default)]
+    pub subjects: Vec<SubjectEmoji>,
+    /// Return the original and transformed images together in one image,
+    /// with the emoji drawn on a badge, instead of the transformed image alone.
+    #[serde(default)]
+    pub composite: Option<CompositeLayout>,
+    /// Pixels of border around and between the images of a composite.
+    #[serde(default)]
+    pub composite_padding: Option<u32>,
+}
+
+/// The emoji to give one subject of a group photo.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubjectEmoji {
+    pub subject: Subject,
+    /// Emoji or blend, as in `TransformRequest::emoji`.
     pub emoji: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransformResponse {
+    /// Transformed image as a `data:<mime>;base64,...` URL; the first variation when several were requested.
     pub transformed_image: String,
+    /// Every variation, present only when more than one was requested.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<TransformVariant>,
     pub metadata: TransformMetadata,
 }
 
+/// One of several variations generated for the same request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransformVariant {
+    pub index: u32,
+    /// Transformed image as a `data:<mime>;base64,...` URL.
+    pub transformed_image: String,
+    pub width: u32,
+    pub height: u32,
+    /// Metadata removed from this variation before it was returned.
+    pub metadata_removed: Vec<MetadataKind>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransformMetadata {
     pub processing_time_ms: u64,
     pub model_version: String,
     pub request_id: String,
+    /// Pixel dimensions of the image sent to the model, after orientation and downscaling.
+    pub input_width: u32,
+    pub input_height: u32,
+    pub metadata_removed: MetadataRemoved,
+    /// Variations returned, which can be fewer than requested when some failed.
+    /// Only the returned ones count against the rate limit.
+    pub variations: u32,
+    /// Expression intensity the prompt was built with.
+    pub intensity: Intensity,
+    /// Version of the prompt templates the request was built with.
+    pub prompt_version: String,
+    /// Subject the edit was confined to, if the request targeted one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<Subject>,
+    /// How each subject of a `subjects` request fared, in the order they were edited.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub subjects: Vec<SubjectOutcome>,
+    /// How the returned images were composited, if the request asked for it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub composite: Option<CompositeMetadata>,
+    /// Name of the API key the request was made with, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tier: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompositeMetadata {
+    pub layout: CompositeLayout,
+    pub padding: u32,
+    /// Whether the emoji badge was drawn. It is left out when the expression
+    /// was only described, or when the emoji images couldn't be fetched.
+    pub badge: bool,
+    /// Why the emoji images couldn't be fetched, when that left the badge out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub badge_error: Option<String>,
+}
+
+/// Result of editing one subject of a group photo.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubjectOutcome {
+    pub subject: Subject,
+    /// The emoji or blend in canonical form.
+    pub emoji: String,
+    /// Whether the edit succeeded. Failed subjects are left as they were and
+    /// don't count against the rate limit.
+    pub edited: bool,
+    pub processing_time_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Embedded metadata found and stripped, so clients can tell nothing identifying was kept.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetadataRemoved {
+    /// Removed from the upload before it was sent to the model.
+    pub input: Vec<MetadataKind>,
+    /// Removed from the model's output before it was returned, across every variation.
+    pub output: Vec<MetadataKind>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AnimateRequest {
+    pub image: ImageUpload,
+    /// Emoji or blend the animation ends on; may be left empty when `description` is given.
+    #[serde(default)]
+    pub emoji: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub subject: Option<Subject>,
+    /// Frames in the animation, counting the unedited original; 4 when absent.
+    #[serde(default)]
+    pub frames: Option<u32>,
+    /// How long each frame is shown, in milliseconds; 250 when absent.
+    #[serde(default)]
+    pub delay_ms: Option<u32>,
+    /// Times the animation plays; 0, the default, loops forever.
+    #[serde(default)]
+    pub loops: Option<u16>,
+    /// `gif` (the default), `apng` or `webp`.
+    #[serde(default)]
+    pub output_format: Option<AnimationFormat>,
+    /// Longest edge of the animation; 512 when absent.
+    #[serde(default)]
+    pub max_dimension: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnimateResponse {
+    /// The animation as a `data:<mime>;base64,...` URL.
+    pub animation: String,
+    pub metadata: AnimateMetadata,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnimateMetadata {
+    pub processing_time_ms: u64,
+    pub model_version: String,
+    pub request_id: String,
+    pub format: AnimationFormat,
+    /// Frames in the animation, counting the original. Frames that failed are
+    /// left out, so this can be fewer than requested; only the generated
+    /// frames that made it in count against the rate limit.
+    pub frames: u32,
+    pub delay_ms: u16,
+    pub loops: u16,
+    pub width: u32,
+    pub height: u32,
+    pub metadata_removed: MetadataRemoved,
+    pub prompt_version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<Subject>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tier: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaResponse {
+    pub limit: Option<u32>,
+    pub remaining: Option<u32>,
+    pub reset: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EmojisResponse {
+    pub emojis: &'static [CatalogEmoji],
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -0,0 +1,86 @@
+use worker::Request;
+
+/// Representation requested for a transformed image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseFormat {
+    /// `TransformResponse` JSON with the image inlined as base64.
+    Json,
+    /// Raw image bytes, with metadata in `X-*` headers.
+    Binary,
+}
+
+impl ResponseFormat {
+    /// Picks the format from `?format=` when given, otherwise from the `Accept` header.
+    pub fn from_request(req: &Request) -> Self {
+        let format = req.url().ok().and_then(|url| {
+            url.query_pairs()
+                .find(|(name, _)| name == "format")
+                .map(|(_, value)| value.into_owned())
+        });
+        let accept = req.headers().get("Accept").ok().flatten();
+
+        Self::negotiate(format.as_deref(), accept.as_deref())
+    }
+
+    fn negotiate(format: Option<&str>, accept: Option<&str>) -> Self {
+        match format.map(|f| f.trim().to_ascii_lowercase()).as_deref() {
+            Some("binary") | Some("image") => return ResponseFormat::Binary,
+            Some("json") => return ResponseFormat::Json,
+            _ => {}
+        }
+
+        let Some(accept) = accept else {
+            return ResponseFormat::Json;
+        };
+
+        let mut image_q: f32 = 0.0;
+        let mut json_q: f32 = 0.0;
+        for entry in accept.split(',') {
+            let mut params = entry.split(';');
+            let media_type = params.next().unwrap_or("").trim().to_ascii_lowercase();
+            let q = params
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.parse().ok())
+                .unwrap_or(1.0);
+
+            if media_type.starts_with("image/") {
+                image_q = image_q.max(q);
+            } else if media_type == "application/json" || media_type == "*/*" {
+                json_q = json_q.max(q);
+            }
+        }
+
+        // JSON wins ties, so generic `Accept` headers keep the original behaviour
+        if image_q > json_q {
+            ResponseFormat::Binary
+        } else {
+            ResponseFormat::Json
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_to_json() {
+        assert_eq!(ResponseFormat::negotiate(None, None), ResponseFormat::Json);
+        assert_eq!(ResponseFormat::negotiate(None, Some("*/*")), ResponseFormat::Json);
+        assert_eq!(ResponseFormat::negotiate(None, Some("application/json")), ResponseFormat::Json);
+    }
+
+    #[test]
+    fn test_accept_image() {
+        assert_eq!(ResponseFormat::negotiate(None, Some("image/png")), ResponseFormat::Binary);
+        assert_eq!(ResponseFormat::negotiate(None, Some("image/*, application/json;q=0.5")), ResponseFormat::Binary);
+        assert_eq!(ResponseFormat::negotiate(None, Some("image/png;q=0.5, application/json")), ResponseFormat::Json);
+        assert_eq!(ResponseFormat::negotiate(None, Some("image/png, */*")), ResponseFormat::Json);
+    }
+
+    #[test]
+    fn test_format_parameter_overrides_accept() {
+        assert_eq!(ResponseFormat::negotiate(Some("binary"), Some("application/json")), ResponseFormat::Binary);
+        assert_eq!(ResponseFormat::negotiate(Some("json"), Some("image/png")), ResponseFormat::Json);
+    }
+}
@@ -0,0 +1,108 @@
+pub mod description;
+pub mod subject;
+pub mod templates;
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// How strongly the emoji's expression is applied.
+///
+/// Accepted by name or as a number from 0.0 to 1.0, which is bucketed into
+/// thirds. The level, not the number, is what the prompt and metadata use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Intensity {
+    Subtle,
+    #[default]
+    Moderate,
+    Extreme,
+}
+
+impl Intensity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Intensity::Subtle => "subtle",
+            Intensity::Moderate => "moderate",
+            Intensity::Extreme => "extreme",
+        }
+    }
+
+    fn from_level(level: f64) -> Result<Self, String> {
+        if !(0.0..=1.0).contains(&level) {
+            return Err("expected a number between 0.0 and 1.0".to_string());
+        }
+
+        Ok(if level < 1.0 / 3.0 {
+            Intensity::Subtle
+        } else if level < 2.0 / 3.0 {
+            Intensity::Moderate
+        } else {
+            Intensity::Extreme
+        })
+    }
+}
+
+impl fmt::Display for Intensity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Intensity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "subtle" => Ok(Intensity::Subtle),
+            "moderate" => Ok(Intensity::Moderate),
+            "extreme" => Ok(Intensity::Extreme),
+            other => match other.parse::<f64>() {
+                Ok(level) => Intensity::from_level(level),
+                Err(_) => Err("expected subtle, moderate, extreme, or a number between 0.0 and 1.0".to_string()),
+            },
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Intensity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Level(f64),
+            Name(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Level(level) => Intensity::from_level(level),
+            Raw::Name(name) => name.parse(),
+        }
+        .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intensity_from_names_and_levels() {
+        assert_eq!("Subtle".parse::<Intensity>().unwrap(), Intensity::Subtle);
+        assert_eq!("extreme".parse::<Intensity>().unwrap(), Intensity::Extreme);
+        assert_eq!("0.1".parse::<Intensity>().unwrap(), Intensity::Subtle);
+        assert_eq!("0.5".parse::<Intensity>().unwrap(), Intensity::Moderate);
+        assert_eq!("1".parse::<Intensity>().unwrap(), Intensity::Extreme);
+        assert!("1.5".parse::<Intensity>().is_err());
+        assert!("furious".parse::<Intensity>().is_err());
+    }
+
+    #[test]
+    fn test_intensity_json() {
+        assert_eq!(serde_json::from_str::<Intensity>("0.9").unwrap(), Intensity::Extreme);
+        assert_eq!(serde_json::from_str::<Intensity>(r#""subtle""#).unwrap(), Intensity::Subtle);
+        assert!(serde_json::from_str::<Intensity>("-0.2").is_err());
+        assert_eq!(serde_json::to_string(&Intensity::Moderate).unwrap(), r#""moderate""#);
+    }
+}
@@ -0,0 +1,218 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Highest subject index accepted; photos rarely hold more faces than this.
+pub const MAX_SUBJECT_INDEX: u32 = 19;
+
+/// Area of a photo as fractions (0.0-1.0) of its width and height, measured
+/// from the top-left corner of the image as displayed.
+///
+/// Fractions rather than pixels, so a box found on the original upload still
+/// fits after orientation is applied and the image is downscaled.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BoundingBox {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl BoundingBox {
+    fn validate(self) -> Result<Self, String> {
+        let fields = [self.x, self.y, self.width, self.height];
+        if !fields.iter().all(|v| (0.0..=1.0).contains(v)) {
+            return Err("box coordinates must be fractions of the image between 0.0 and 1.0".to_string());
+        }
+        if self.width == 0.0 || self.height == 0.0 {
+            return Err("box must have a non-zero width and height".to_string());
+        }
+        // Tolerates rounding in boxes that end exactly on the edge
+        if self.x + self.width > 1.0 + 1e-6 || self.y + self.height > 1.0 + 1e-6 {
+            return Err("box must lie within the image".to_string());
+        }
+        Ok(self)
+    }
+}
+
+/// Which subject to edit in a photo with several faces.
+///
+/// Given as an index counted from the left, or as a box around the face.
+/// Serializes as `{"index": 1}` or `{"region": {...}}` for the response
+/// metadata; requests may also pass the bare index, the box object, or the
+/// `1` / `x,y,width,height` text form used by query parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Subject {
+    /// Position from the left, starting at 0.
+    Index(u32),
+    Region(BoundingBox),
+}
+
+impl Subject {
+    fn validate(self) -> Result<Self, String> {
+        match self {
+            Subject::Index(index) if index > MAX_SUBJECT_INDEX => Err(format!(
+                "subject index must be between 0 and {}, got {}",
+                MAX_SUBJECT_INDEX, index
+            )),
+            Subject::Index(_) => Ok(self),
+            Subject::Region(region) => region.validate().map(Subject::Region),
+        }
+    }
+
+    /// Where the subject is, in words: `that is 2nd from the left`, or
+    /// `whose face is inside the box from 10% to 40% across and 20% to 60% down the photo`.
+    pub fn position(&self) -> String {
+        match self {
+            Subject::Index(index) => format!("that is {} from the left", ordinal(index + 1)),
+            Subject::Region(region) => {
+                let percent = |fraction: f64| (fraction * 100.0).round() as u32;
+                format!(
+                    "whose face is inside the box from {}% to {}% across and {}% to {}% down the photo",
+                    percent(region.x),
+                    percent(region.x + region.width).min(100),
+                    percent(region.y),
+                    percent(region.y + region.height).min(100)
+                )
+            }
+        }
+    }
+}
+
+impl fmt::Display for Subject {
+    /// The text form accepted by [`FromStr`].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Subject::Index(index) => write!(f, "{}", index),
+            Subject::Region(region) => write!(f, "{},{},{},{}", region.x, region.y, region.width, region.height),
+        }
+    }
+}
+
+impl FromStr for Subject {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+        let subject = match parts.as_slice() {
+            [index] => index.parse().map(Subject::Index).map_err(|_| {
+                "expected a subject index, or a box as x,y,width,height fractions".to_string()
+            })?,
+            [x, y, width, height] => {
+                let number = |v: &str| {
+                    v.parse::<f64>()
+                        .map_err(|_| format!("'{}' is not a number; box coordinates are fractions from 0.0 to 1.0", v))
+                };
+                Subject::Region(BoundingBox {
+                    x: number(x)?,
+                    y: number(y)?,
+                    width: number(width)?,
+                    height: number(height)?,
+                })
+            }
+            _ => return Err("expected a subject index, or a box as x,y,width,height fractions".to_string()),
+        };
+        subject.validate()
+    }
+}
+
+impl<'de> Deserialize<'de> for Subject {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "lowercase")]
+        enum Tagged {
+            Index(u32),
+            Region(BoundingBox),
+        }
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Index(u32),
+            Region(BoundingBox),
+            Tagged(Tagged),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Index(index) | Raw::Tagged(Tagged::Index(index)) => Subject::Index(index).validate(),
+            Raw::Region(region) | Raw::Tagged(Tagged::Region(region)) => Subject::Region(region).validate(),
+            Raw::Text(text) => text.parse(),
+        }
+        .map_err(serde::de::Error::custom)
+    }
+}
+
+/// `1st`, `2nd`, `3rd`, `4th`, ..., `11th`, `21st`.
+fn ordinal(n: u32) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(x: f64, y: f64, width: f64, height: f64) -> Subject {
+        Subject::Region(BoundingBox { x, y, width, height })
+    }
+
+    #[test]
+    fn test_subject_from_text() {
+        assert_eq!("2".parse::<Subject>().unwrap(), Subject::Index(2));
+        assert_eq!("0.1, 0.2, 0.3, 0.4".parse::<Subject>().unwrap(), region(0.1, 0.2, 0.3, 0.4));
+        assert!("left".parse::<Subject>().is_err());
+        assert!("0.1,0.2,0.3".parse::<Subject>().is_err());
+        assert!("20".parse::<Subject>().unwrap_err().contains("between 0 and 19"));
+    }
+
+    #[test]
+    fn test_box_must_fit_the_image() {
+        assert!("0.5,0.5,0.5,0.5".parse::<Subject>().is_ok());
+        assert_eq!("0.6,0,0.5,0.5".parse::<Subject>().unwrap_err(), "box must lie within the image");
+        assert!("0.1,0.1,0,0.5".parse::<Subject>().unwrap_err().contains("non-zero"));
+        assert!("-0.1,0,0.5,0.5".parse::<Subject>().is_err());
+        assert!("120,40,200,200".parse::<Subject>().unwrap_err().contains("fractions"));
+    }
+
+    #[test]
+    fn test_subject_json() {
+        assert_eq!(serde_json::from_str::<Subject>("1").unwrap(), Subject::Index(1));
+        assert_eq!(
+            serde_json::from_str::<Subject>(r#"{"x": 0.1, "y": 0.2, "width": 0.3, "height": 0.4}"#).unwrap(),
+            region(0.1, 0.2, 0.3, 0.4)
+        );
+        assert_eq!(serde_json::from_str::<Subject>(r#""3""#).unwrap(), Subject::Index(3));
+        assert!(serde_json::from_str::<Subject>("42").is_err());
+
+        // The metadata form reads back as the same subject
+        let subject = region(0.25, 0.0, 0.5, 1.0);
+        let json = serde_json::to_string(&subject).unwrap();
+        assert_eq!(json, r#"{"region":{"x":0.25,"y":0.0,"width":0.5,"height":1.0}}"#);
+        assert_eq!(serde_json::from_str::<Subject>(&json).unwrap(), subject);
+        assert_eq!(serde_json::to_string(&Subject::Index(0)).unwrap(), r#"{"index":0}"#);
+    }
+
+    #[test]
+    fn test_position() {
+        assert_eq!(Subject::Index(0).position(), "that is 1st from the left");
+        assert_eq!(Subject::Index(1).position(), "that is 2nd from the left");
+        assert_eq!(Subject::Index(10).position(), "that is 11th from the left");
+        assert_eq!(Subject::Index(11).position(), "that is 12th from the left");
+        assert_eq!(Subject::Index(12).position(), "that is 13th from the left");
+        assert_eq!(Subject::Index(2).position(), "that is 3rd from the left");
+        assert_eq!(
+            region(0.1, 0.2, 0.3, 0.4).position(),
+            "whose face is inside the box from 10% to 40% across and 20% to 60% down the photo"
+        );
+        assert_eq!(region(0.1, 0.2, 0.3, 0.4).to_string().parse::<Subject>().unwrap(), region(0.1, 0.2, 0.3, 0.4));
+    }
+}
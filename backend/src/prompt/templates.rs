@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use worker::Env;
+
+use super::description::Description;
+use super::subject::Subject;
+use super::Intensity;
+use crate::emoji::catalog::{self, CatalogEmoji};
+use crate::emoji::{BlendComponent, EmojiBlend};
+use crate::error::{AppError, AppResult};
+
+pub const BUILTIN_VERSION: &str = "builtin-2";
+const KV_KEY: &str = "prompt_templates";
+
+/// Wording of the edit instruction sent to the model.
+///
+/// The intensity templates take two placeholders:
+///
+/// - `{target}`: the emoji with its expression, e.g. `this emoji: 😤 (huffing ...)`,
+///   the weighted list of a blend, or the `described` text when there is no emoji
+/// - `{subject}`: `emoji`, `emojis` for a blend, or `description`
+///
+/// `step`, used for the frames of an animation, takes them too, plus
+/// `{percent}`: how far along the way to the target expression the frame is.
+///
+/// `described` and `description` take the client's text as `{description}`,
+/// and `region` takes where the targeted subject is as `{position}`.
+///
+/// Overrides may leave out any field except `version`, which is reported in
+/// the response metadata so results can be tied back to the wording used.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct PromptTemplates {
+    pub version: String,
+    /// Share of traffic this set gets when several are configured.
+    pub weight: u32,
+    pub subtle: String,
+    pub moderate: String,
+    pub extreme: String,
+    pub step: String,
+    /// Appended when the request blends several emojis.
+    pub blend: String,
+    /// Stands in for `{target}` when a description is given without an emoji.
+    pub described: String,
+    /// Appended when a description is given alongside an emoji.
+    pub description: String,
+    /// Appended when the request targets one subject of several.
+    pub region: String,
+    /// Expression descriptions replacing the catalog's, keyed by emoji.
+    pub expressions: HashMap<String, String>,
+}
+
+impl Default for PromptTemplates {
+    fn default() -> Self {
+        Self {
+            version: BUILTIN_VERSION.to_string(),
+            weight: 1,
+            subtle: "Please edit this photo by giving the subject's facial expression a faint, subtle hint of {target}. Keep the change understated and natural, like a slight smirk or a barely raised brow, and keep everything else the same.".to_string(),
+            moderate: "Please edit this photo by changing the subject's facial expression to look more like {target}. Make the facial expression match the mood of the {subject} while keeping everything else the same.".to_string(),
+            extreme: "Please edit this photo by exaggerating the subject's facial expression into an over-the-top, cartoonish version of {target}. Push the emotion as far as it will go while keeping them recognizable and everything else the same.".to_string(),
+            step: "Please edit this photo by changing the subject's facial expression {percent}% of the way from how it looks now to {target}. At 100% the face fully matches the mood of the {subject}; keep everything else, including the pose, lighting and framing, exactly the same.".to_string(),
+            blend: "Mix the expressions in those proportions, so the largest share sets the overall mood and the others tint it.".to_string(),
+            described: "this expression: \"{description}\"".to_string(),
+            description: "Shape the expression so it also fits this description: \"{description}\".".to_string(),
+            region: "Only edit the face of the subject {position}, and leave every other face in the photo exactly as it is.".to_string(),
+            expressions: HashMap::new(),
+        }
+    }
+}
+
+impl PromptTemplates {
+    /// Builds the edit instruction for an emoji (or blend), a description, or
+    /// both, at `intensity`. At least one of them must be given. With a
+    /// `subject`, the edit is confined to that one.
+    pub fn render(
+        &self,
+        blend: Option<&EmojiBlend>,
+        description: Option<&Description>,
+        intensity: Intensity,
+        subject: Option<&Subject>,
+    ) -> String {
+        let template = match intensity {
+            Intensity::Subtle => &self.subtle,
+            Intensity::Moderate => &self.moderate,
+            Intensity::Extreme => &self.extreme,
+        };
+        self.render_with(template, blend, description, subject)
+    }
+
+    /// Like [`render`](Self::render), for one frame of an animation that is
+    /// `percent` of the way to the target expression.
+    pub fn render_step(
+        &self,
+        blend: Option<&EmojiBlend>,
+        description: Option<&Description>,
+        percent: u32,
+        subject: Option<&Subject>,
+    ) -> String {
+        let template = self.step.replace("{percent}", &percent.to_string());
+        self.render_with(&template, blend, description, subject)
+    }
+
+    fn render_with(
+        &self,
+        template: &str,
+        blend: Option<&EmojiBlend>,
+        description: Option<&Description>,
+        subject: Option<&Subject>,
+    ) -> String {
+        let with_description = |text: &str| {
+            text.replace("{description}", description.map_or("", Description::as_str))
+        };
+        let (target, source) = match blend {
+            Some(blend) if blend.is_blend() => (self.describe_target(blend), "emojis"),
+            Some(blend) => (self.describe_target(blend), "emoji"),
+            None => (with_description(&self.described), "description"),
+        };
+
+        let mut prompt = template.replace("{target}", &target).replace("{subject}", source);
+        if blend.is_some_and(EmojiBlend::is_blend) {
+            prompt = format!("{} {}", prompt, self.blend);
+        }
+        if blend.is_some() && description.is_some() {
+            prompt = format!("{} {}", prompt, with_description(&self.description));
+        }
+        if let Some(subject) = subject {
+            prompt = format!("{} {}", prompt, self.region.replace("{position}", &subject.position()));
+        }
+        prompt
+    }
+
+    fn expression(&self, entry: &CatalogEmoji) -> &str {
+        self.expressions
+            .get(entry.emoji)
+            .map_or(entry.expression, String::as_str)
+    }
+
+    /// `this emoji: 😊 (a warm ... smile)`, or
+    /// `a blend of these emojis: 70% 😂 (laughing ...) and 30% 😭 (sobbing ...)`.
+    fn describe_target(&self, blend: &EmojiBlend) -> String {
+        let describe = |c: &BlendComponent| format!("{} ({})", c.entry.emoji, self.expression(c.entry));
+
+        let [rest @ .., last] = blend.components.as_slice() else {
+            return String::new();
+        };
+        if rest.is_empty() {
+            return format!("this emoji: {}", describe(last));
+        }
+
+        let shares: Vec<String> = rest.iter().map(|c| format!("{}% {}", c.weight, describe(c))).collect();
+        format!(
+            "a blend of these emojis: {} and {}% {}",
+            shares.join(", "),
+            last.weight,
+            describe(last)
+        )
+    }
+
+    fn validate(mut self) -> Result<Self, String> {
+        if self.version.trim().is_empty() || self.version == BUILTIN_VERSION {
+            return Err("prompt template overrides must set their own version".to_string());
+        }
+
+        let intensities = [("subtle", &self.subtle), ("moderate", &self.moderate), ("extreme", &self.extreme)];
+        for (name, template) in intensities.into_iter().chain([("step", &self.step)]) {
+            if !template.contains("{target}") {
+                return Err(format!("template '{}' of version '{}' has no {{target}} placeholder", name, self.version));
+            }
+        }
+        for (name, template) in [("described", &self.described), ("description", &self.description)] {
+            if !template.contains("{description}") {
+                return Err(format!(
+                    "template '{}' of version '{}' has no {{description}} placeholder",
+                    name, self.version
+                ));
+            }
+        }
+
+        if !self.step.contains("{percent}") {
+            return Err(format!("template 'step' of version '{}' has no {{percent}} placeholder", self.version));
+        }
+        if !self.region.contains("{position}") {
+            return Err(format!("template 'region' of version '{}' has no {{position}} placeholder", self.version));
+        }
+
+        // Keyed by the catalog's spelling so lookups match parsed emojis
+        let mut expressions = HashMap::with_capacity(self.expressions.len());
+        for (emoji, expression) in self.expressions {
+            let entry = catalog::lookup(&emoji)
+                .ok_or_else(|| format!("expression override for '{}', which is not in the catalog", emoji))?;
+            expressions.insert(entry.emoji.to_string(), expression);
+        }
+        self.expressions = expressions;
+
+        Ok(self)
+    }
+}
+
+/// The template sets prompts are built from.
+///
+/// Read from the `prompt_templates` key of the `PROMPTS_KV` namespace when it
+/// is bound, so wording can change without a deploy, then from the
+/// `PROMPT_TEMPLATES` variable, falling back to the built-in wording. Either
+/// holds one template set or an array of them; with several, each request
+/// picks one at random in proportion to its `weight` for A/B testing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptConfig {
+    sets: Vec<PromptTemplates>,
+}
+
+impl Default for PromptConfig {
+    fn default() -> Self {
+        Self { sets: vec![PromptTemplates::default()] }
+    }
+}
+
+impl PromptConfig {
+    pub async fn from_env(env: &Env) -> AppResult<Self> {
+        let kv_value = match env.kv("PROMPTS_KV") {
+            Ok(kv) => kv.get(KV_KEY).text().await?,
+            Err(_) => None,
+        };
+        let value = kv_value.or_else(|| env.var("PROMPT_TEMPLATES").ok().map(|v| v.to_string()));
+
+        match value {
+            Some(json) => Self::from_json(&json)
+                .map_err(|e| AppError::InternalError(format!("Invalid prompt templates: {}", e))),
+            None => Ok(Self::default()),
+        }
+    }
+
+    fn from_json(json: &str) -> Result<Self, String> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            One(Box<PromptTemplates>),
+            Many(Vec<PromptTemplates>),
+        }
+
+        let sets = match serde_json::from_str(json).map_err(|e| e.to_string())? {
+            Raw::One(set) => vec![*set],
+            Raw::Many(sets) => sets,
+        };
+        let sets = sets
+            .into_iter()
+            .map(PromptTemplates::validate)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if sets.iter().map(|set| u64::from(set.weight)).sum::<u64>() == 0 {
+            return Err("at least one template set needs a positive weight".to_string());
+        }
+
+        Ok(Self { sets })
+    }
+
+    /// Picks a template set; `roll` is a random number that spreads requests
+    /// across the sets by weight.
+    pub fn choose(&self, roll: u64) -> &PromptTemplates {
+        let total: u64 = self.sets.iter().map(|set| u64::from(set.weight)).sum();
+        let mut point = roll % total.max(1);
+        for set in &self.sets {
+            if point < u64::from(set.weight) {
+                return set;
+            }
+            point -= u64::from(set.weight);
+        }
+        &self.sets[0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(emoji: &str, intensity: Intensity) -> String {
+        PromptTemplates::default().render(Some(&EmojiBlend::parse(emoji).unwrap()), None, intensity, None)
+    }
+
+    fn description(text: &str) -> Description {
+        Description::parse(Some(text)).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_prompt_varies_with_intensity() {
+        let subtle = render("😠", Intensity::Subtle);
+        let extreme = render("😠", Intensity::Extreme);
+        assert!(subtle.contains("this emoji: 😠 (angry, with furrowed brows and a firm frown)"));
+        assert!(subtle.contains("subtle"));
+        assert!(extreme.contains("this emoji: 😠") && extreme.contains("over-the-top"));
+        assert_ne!(subtle, render("😠", Intensity::Moderate));
+    }
+
+    #[test]
+    fn test_prompt_describes_blend() {
+        let moderate = render("😂 70% + 😭 30%", Intensity::Moderate);
+        assert!(moderate.contains("a blend of these emojis: 70% 😂 (laughing"));
+        assert!(moderate.contains(" and 30% 😭 (sobbing loudly with the mouth wide open and streams of tears)."));
+        assert!(moderate.contains("mood of the emojis"));
+        assert!(moderate.ends_with("the others tint it."));
+
+        let three = render("😂 + 😭 + 😡", Intensity::Subtle);
+        assert!(three.contains("34% 😂 ("));
+        assert!(three.contains("), 33% 😭 ("));
+        assert!(three.contains(") and 33% 😡 ("));
+    }
+
+    #[test]
+    fn test_prompt_describes_zwj_sequence() {
+        let prompt = render("😵\u{200D}💫 + 😴", Intensity::Moderate);
+        assert!(prompt.contains("50% 😵\u{200D}💫 (dazed and hypnotized, with spiraling eyes and a wobbly mouth)"));
+    }
+
+    #[test]
+    fn test_description_alone_or_with_emoji() {
+        let templates = PromptTemplates::default();
+        let smug = description("smug but tired");
+
+        let alone = templates.render(None, Some(&smug), Intensity::Moderate, None);
+        assert!(alone.contains("to look more like this expression: \"smug but tired\". "));
+        assert!(alone.contains("mood of the description"));
+
+        let blend = EmojiBlend::parse("😏").unwrap();
+        let combined = templates.render(Some(&blend), Some(&smug), Intensity::Subtle, None);
+        assert!(combined.contains("this emoji: 😏 ("));
+        assert!(combined.ends_with("also fits this description: \"smug but tired\"."));
+    }
+
+    #[test]
+    fn test_subject_confines_the_edit() {
+        let templates = PromptTemplates::default();
+        let blend = EmojiBlend::parse("😊").unwrap();
+
+        let untargeted = templates.render(Some(&blend), None, Intensity::Moderate, None);
+        assert!(untargeted.contains("the subject's facial expression"));
+        assert!(!untargeted.contains("every other face"));
+
+        let second = templates.render(Some(&blend), None, Intensity::Moderate, Some(&Subject::Index(1)));
+        assert!(second.starts_with(&untargeted));
+        assert!(second.ends_with(
+            "Only edit the face of the subject that is 2nd from the left, and leave every other face in the photo exactly as it is."
+        ));
+
+        let boxed: Subject = "0.5,0.1,0.25,0.4".parse().unwrap();
+        let boxed = templates.render(Some(&blend), None, Intensity::Subtle, Some(&boxed));
+        assert!(boxed.contains("subject whose face is inside the box from 50% to 75% across and 10% to 50% down the photo, "));
+    }
+
+    #[test]
+    fn test_step_prompt_for_animation_frames() {
+        let templates = PromptTemplates::default();
+        let blend = EmojiBlend::parse("😡").unwrap();
+
+        let halfway = templates.render_step(Some(&blend), None, 50, None);
+        assert!(halfway.contains("50% of the way from how it looks now to this emoji: 😡 (red-faced rage"));
+        assert!(halfway.contains("mood of the emoji;"));
+
+        let targeted = templates.render_step(Some(&blend), None, 100, Some(&Subject::Index(0)));
+        assert!(targeted.contains("100% of the way"));
+        assert!(targeted.ends_with("exactly as it is."));
+    }
+
+    #[test]
+    fn test_override_replaces_wording_and_expressions() {
+        let config = PromptConfig::from_json(
+            r#"{
+                "version": "terse-2",
+                "moderate": "Make the face show {target}.",
+                "expressions": {"☹": "a gloomy pout"}
+            }"#,
+        )
+        .unwrap();
+        let templates = config.choose(0);
+        assert_eq!(templates.version, "terse-2");
+
+        let blend = EmojiBlend::parse("☹️").unwrap();
+        assert_eq!(
+            templates.render(Some(&blend), None, Intensity::Moderate, None),
+            "Make the face show this emoji: ☹️ (a gloomy pout)."
+        );
+        // Fields left out keep the built-in wording
+        assert_eq!(templates.subtle, PromptTemplates::default().subtle);
+    }
+
+    #[test]
+    fn test_weighted_sets_split_traffic() {
+        let config = PromptConfig::from_json(
+            r#"[{"version": "a", "weight": 3}, {"version": "b", "weight": 1}, {"version": "off", "weight": 0}]"#,
+        )
+        .unwrap();
+        let picks: Vec<&str> = (0..8).map(|roll| config.choose(roll).version.as_str()).collect();
+        assert_eq!(picks, ["a", "a", "a", "b", "a", "a", "a", "b"]);
+    }
+
+    #[test]
+    fn test_invalid_overrides() {
+        let error = |json: &str| PromptConfig::from_json(json).unwrap_err();
+        assert!(error(r#"{"moderate": "Smile {target}"}"#).contains("own version"));
+        assert!(error(r#"{"version": "x", "extreme": "Be angry"}"#).contains("{target}"));
+        assert!(error(r#"{"version": "x", "expressions": {"👍": "thumbs"}}"#).contains("not in the catalog"));
+        assert!(error(r#"{"version": "x", "described": "this look"}"#).contains("{description}"));
+        assert!(error(r#"{"version": "x", "region": "Only the left one."}"#).contains("{position}"));
+        assert!(error(r#"{"version": "x", "step": "Go {target}"}"#).contains("{percent}"));
+        assert!(error(r#"[{"version": "x", "weight": 0}]"#).contains("positive weight"));
+        assert!(error("not json").contains("expected"));
+    }
+
+    #[test]
+    fn test_default_config_uses_builtin() {
+        assert_eq!(PromptConfig::default().choose(12345).version, BUILTIN_VERSION);
+    }
+}
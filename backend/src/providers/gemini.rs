@@ -1,8 +1,12 @@
-use crate::error::AppError;
+use crate::error::{AppError, AppResult};
+use crate::providers::{collect_candidates, EditResponse, EditedImage, ImageEditProvider, InputImage, ProviderFeatures, UnifiedEditRequest};
+use async_trait::async_trait;
+use futures::future::join_all;
 use serde::{Deserialize, Serialize};
-use worker::{Env, Fetch, Headers, Method, Request as WorkerRequest, Result};
+use worker::{Env, Fetch, Headers, Method, Request as WorkerRequest};
 
-const GEMINI_API_URL: &str = "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.5-flash-image-preview:generateContent";
+const DEFAULT_GEMINI_MODEL: &str = "gemini-2.5-flash-image-preview";
+const DEFAULT_GEMINI_API_BASE_URL: &str = "https://generativelanguage.googleapis.com/v1beta";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct GeminiRequest {
@@ -45,26 +49,86 @@ struct GeminiCandidate {
     finish_reason: Option<String>,
 }
 
+/// Builds the `generateContent` endpoint for `model` under `base_url`.
+fn generate_content_url(base_url: &str, model: &str) -> String {
+    format!("{}/models/{}:generateContent", base_url.trim_end_matches('/'), model)
+}
+
+/// Maps an HTTP error status from the Gemini API to the matching `AppError`.
+pub(crate) fn status_error(status: u16, error_text: &str) -> AppError {
+    match status {
+        400 => AppError::GeminiInvalidRequest(format!("Invalid request to Gemini API: {}", error_text)),
+        401 | 403 => AppError::GeminiApiError("Authentication failed with Gemini API".to_string()),
+        429 => AppError::GeminiQuotaExceeded("Gemini API quota exceeded".to_string()),
+        500..=599 => AppError::GeminiApiError(format!("Gemini API server error: {}", error_text)),
+        _ => AppError::GeminiApiError(format!("Gemini API error: {}", error_text)),
+    }
+}
+
+/// Maps a candidate `finishReason` to an error, or `None` when the reason
+/// does not prevent an image from being returned.
+pub(crate) fn finish_reason_error(finish_reason: &str) -> Option<AppError> {
+    match finish_reason {
+        "PROHIBITED_CONTENT" => Some(AppError::GeminiContentFiltered("This content was flagged as inappropriate by Google's Gemini AI service".to_string())),
+        "SAFETY" => Some(AppError::GeminiContentFiltered("This content violated Google's safety guidelines".to_string())),
+        "RECITATION" => Some(AppError::TransformationFailed("Gemini could not process this type of content".to_string())),
+        "OTHER" => Some(AppError::TransformationFailed("Gemini encountered an unknown error".to_string())),
+        _ => None,
+    }
+}
+
+/// Builds the `generateContent` body for an edit of `image`, labelled with its detected MIME type.
+fn edit_request(image: &InputImage, prompt: &str) -> GeminiRequest {
+    GeminiRequest {
+        contents: vec![GeminiContent {
+            parts: vec![
+                GeminiPart::Text { text: prompt.to_string() },
+                GeminiPart::Image {
+                    inline_data: InlineData {
+                        mime_type: image.mime_type.clone(),
+                        data: image.data.clone(),
+                    },
+                },
+            ],
+        }],
+    }
+}
+
 pub struct GeminiProvider {
     api_key: String,
+    api_url: String,
+    model: String,
 }
 
 impl GeminiProvider {
-    pub fn new(env: &Env) -> Result<Self> {
+    pub fn new(env: &Env) -> AppResult<Self> {
         let api_key = if let Ok(secret) = env.secret("GEMINI_API_KEY") {
             secret.to_string()
         } else if let Ok(var) = env.var("GEMINI_API_KEY") {
             var.to_string()
         } else {
-            return Err(worker::Error::RustError(
+            return Err(AppError::InternalError(
                 "GEMINI_API_KEY not configured as secret or environment variable".to_string(),
             ));
         };
 
-        Ok(Self { api_key })
+        let base_url = env
+            .var("GEMINI_API_BASE_URL")
+            .map(|v| v.to_string())
+            .unwrap_or_else(|_| DEFAULT_GEMINI_API_BASE_URL.to_string());
+        let model = env
+            .var("GEMINI_MODEL")
+            .map(|v| v.to_string())
+            .unwrap_or_else(|_| DEFAULT_GEMINI_MODEL.to_string());
+
+        Ok(Self {
+            api_key,
+            api_url: generate_content_url(&base_url, &model),
+            model,
+        })
     }
 
-    async fn call_gemini_api(&self, request_body: GeminiRequest) -> Result<GeminiResponse> {
+    async fn call_gemini_api(&self, request_body: GeminiRequest) -> AppResult<GeminiResponse> {
         let headers = Headers::new();
         headers.set("x-goog-api-key", &self.api_key)?;
         headers.set("Content-Type", "application/json")?;
@@ -76,7 +140,7 @@ impl GeminiProvider {
                 &serde_json::to_string(&request_body)?,
             )));
 
-        let request = WorkerRequest::new_with_init(GEMINI_API_URL, &init)?;
+        let request = WorkerRequest::new_with_init(&self.api_url, &init)?;
         let mut response = Fetch::Request(request).send().await?;
 
         if response.status_code() >= 400 {
@@ -85,15 +149,7 @@ impl GeminiProvider {
                 .await
                 .unwrap_or_else(|_| "Unknown error".to_string());
 
-            let error = match response.status_code() {
-                400 => AppError::GeminiInvalidRequest(format!("Invalid request to Gemini API: {}", error_text)),
-                401 | 403 => AppError::GeminiApiError("Authentication failed with Gemini API".to_string()),
-                429 => AppError::GeminiQuotaExceeded("Gemini API quota exceeded".to_string()),
-                500..=599 => AppError::GeminiApiError(format!("Gemini API server error: {}", error_text)),
-                _ => AppError::GeminiApiError(format!("Gemini API error: {}", error_text)),
-            };
-
-            return Err(error.into());
+            return Err(status_error(response.status_code(), &error_text));
         }
 
         let response_text = response
@@ -112,80 +168,37 @@ impl GeminiProvider {
         Ok(gemini_response)
     }
 
-    pub async fn transform_image(&self, image_data: &str, emoji: &str) -> Result<String> {
+    async fn transform_image(&self, image: &InputImage, prompt: &str) -> AppResult<InlineData> {
         const MAX_RETRIES: u32 = 3;
-        let mut last_error: Option<AppError> = None;
+        let mut attempt = 1;
 
-        for attempt in 1..=MAX_RETRIES {
-            let result = self.try_transform_once(image_data, emoji).await;
-
-            match result {
-                Ok(image_data) => return Ok(image_data),
-                Err(e) => {
-                    let error_msg = e.to_string();
-
-                    if error_msg.contains("PROHIBITED_CONTENT") || error_msg.contains("Content flagged as inappropriate") {
-                        last_error = Some(AppError::InternalError(error_msg));
-                        if attempt < MAX_RETRIES {
-                            continue;
-                        }
-                    } else {
-                        return Err(e);
-                    }
-                }
+        // Content filtering is occasionally inconsistent for identical input, so
+        // filtered responses are retried before giving up.
+        loop {
+            match self.try_transform_once(image, prompt).await {
+                Err(AppError::GeminiContentFiltered(_)) if attempt < MAX_RETRIES => attempt += 1,
+                result => return result,
             }
         }
-
-        Err(last_error.unwrap_or(AppError::InternalError("Failed after retries".to_string())).into())
     }
 
-    async fn try_transform_once(&self, image_data: &str, emoji: &str) -> Result<String> {
-        let prompt = format!("Please edit this photo by changing the person's facial expression to look more like this emoji: {}. Make the facial expression match the mood of the emoji while keeping everything else the same.", emoji);
-
-        let gemini_request = GeminiRequest {
-            contents: vec![GeminiContent {
-                parts: vec![
-                    GeminiPart::Text { text: prompt },
-                    GeminiPart::Image {
-                        inline_data: InlineData {
-                            mime_type: "image/jpeg".to_string(),
-                            data: image_data.to_string(),
-                        },
-                    },
-                ],
-            }],
-        };
-
-        let response = self.call_gemini_api(gemini_request).await?;
+    async fn try_transform_once(&self, image: &InputImage, prompt: &str) -> AppResult<InlineData> {
+        let response = self.call_gemini_api(edit_request(image, prompt)).await?;
 
         if response.candidates.is_empty() {
-            return Err(AppError::InternalError("No response from Gemini".to_string()).into());
+            return Err(AppError::InternalError("No response from Gemini".to_string()));
         }
 
         for candidate in response.candidates.iter() {
-            if let Some(finish_reason) = &candidate.finish_reason {
-                match finish_reason.as_str() {
-                    "PROHIBITED_CONTENT" => {
-                        return Err(AppError::GeminiContentFiltered("This content was flagged as inappropriate by Google's Gemini AI service".to_string()).into());
-                    }
-                    "SAFETY" => {
-                        return Err(AppError::GeminiContentFiltered("This content violated Google's safety guidelines".to_string()).into());
-                    }
-                    "RECITATION" => {
-                        return Err(AppError::TransformationFailed("Gemini could not process this type of content".to_string()).into());
-                    }
-                    "OTHER" => {
-                        return Err(AppError::TransformationFailed("Gemini encountered an unknown error".to_string()).into());
-                    }
-                    _ => {}
-                }
+            if let Some(error) = candidate.finish_reason.as_deref().and_then(finish_reason_error) {
+                return Err(error);
             }
 
             if let Some(content) = &candidate.content {
                 for part in content.parts.iter() {
                     match part {
                         GeminiPart::Image { inline_data } => {
-                            return Ok(inline_data.data.clone());
+                            return Ok(inline_data.clone());
                         }
                         GeminiPart::Text { .. } => {}
                     }
@@ -194,8 +207,106 @@ impl GeminiProvider {
         }
         Err(AppError::TransformationFailed(
             "Gemini did not return an image. Try a different photo or emoji.".to_string(),
-        )
-        .into())
+        ))
+    }
+}
+
+#[async_trait(?Send)]
+impl ImageEditProvider for GeminiProvider {
+    fn name(&self) -> &'static str {
+        "gemini"
+    }
+
+    fn model_version(&self) -> String {
+        self.model.clone()
+    }
+
+    fn get_supported_features(&self) -> ProviderFeatures {
+        ProviderFeatures {
+            supports_edit: true,
+        }
+    }
+
+    async fn edit_image(&self, request: &UnifiedEditRequest) -> AppResult<EditResponse> {
+        let image = request
+            .image
+            .first()
+            .ok_or_else(|| AppError::BadRequest("No image provided for editing".to_string()))?;
+
+        // Each candidate is a separate generateContent call; the image model
+        // only ever returns one image per call.
+        let candidates = (0..request.n).map(|_| async {
+            let inline_data = self.transform_image(image, &request.prompt).await?;
+            Ok(EditedImage {
+                data: inline_data.data,
+                mime_type: inline_data.mime_type,
+            })
+        });
+
+        let images = collect_candidates(join_all(candidates).await)?;
+        Ok(EditResponse { images })
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imaging::{fixtures, DecodedImage};
+
+    fn inline_mime_type(bytes: Vec<u8>) -> String {
+        let image = InputImage::from_decoded(&DecodedImage::inspect(bytes).unwrap());
+        let body = serde_json::to_value(edit_request(&image, "prompt")).unwrap();
+        body["contents"][0]["parts"][1]["inlineData"]["mimeType"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_edit_request_labels_detected_mime_type() {
+        let png = fixtures::png(32, 16);
+        // Colour type 6: truecolour with alpha
+        assert_eq!(png[25], 6);
+
+        assert_eq!(inline_mime_type(png), "image/png");
+        assert_eq!(inline_mime_type(fixtures::webp(32, 16)), "image/webp");
+        assert_eq!(inline_mime_type(fixtures::jpeg(32, 16)), "image/jpeg");
+        assert_eq!(inline_mime_type(fixtures::heic(32, 16)), "image/heic");
+    }
+
+    #[test]
+    fn test_edit_request_layout() {
+        let image = InputImage {
+            data: "aW5wdXQ=".to_string(),
+            mime_type: "image/png".to_string(),
+        };
+        let body = serde_json::to_value(edit_request(&image, "make it smile")).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "contents": [{
+                    "parts": [
+                        { "text": "make it smile" },
+                        { "inlineData": { "mimeType": "image/png", "data": "aW5wdXQ=" } }
+                    ]
+                }]
+            })
+        );
+    }
+
+    #[test]
+    fn test_generate_content_url_default() {
+        assert_eq!(
+            generate_content_url(DEFAULT_GEMINI_API_BASE_URL, DEFAULT_GEMINI_MODEL),
+            "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.5-flash-image-preview:generateContent"
+        );
+    }
+
+    #[test]
+    fn test_generate_content_url_trailing_slash() {
+        assert_eq!(
+            generate_content_url("http://localhost:9000/v1beta/", "fake-model"),
+            "http://localhost:9000/v1beta/models/fake-model:generateContent"
+        );
+    }
+}
@@ -0,0 +1,210 @@
+use crate::error::{AppError, AppResult};
+use crate::providers::gemini::{finish_reason_error, status_error};
+use crate::providers::{collect_candidates, EditResponse, EditedImage, ImageEditProvider, ProviderFeatures, UnifiedEditRequest};
+use async_trait::async_trait;
+use std::str::FromStr;
+use worker::Env;
+
+const MOCK_MODEL: &str = "mock-image-editor";
+
+/// 1x1 transparent PNG returned by the `success` scenario.
+const CANNED_PNG: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mP8z8BQDwAEhQGAhKmMIQAAAABJRU5ErkJggg==";
+
+/// Outcome the mock provider produces, configured through `MOCK_PROVIDER_SCENARIO`.
+///
+/// Accepted values are `success`, `echo`, `empty`, `no_image`, `timeout`,
+/// `no_faces`, `processing_failed`, `finish:<REASON>` and `status:<CODE>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockScenario {
+    Success,
+    Echo,
+    Empty,
+    NoImage,
+    Timeout,
+    NoFaces,
+    ProcessingFailed,
+    FinishReason(String),
+    HttpStatus(u16),
+}
+
+impl FromStr for MockScenario {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if let Some(reason) = s.strip_prefix("finish:") {
+            return Ok(MockScenario::FinishReason(reason.trim().to_ascii_uppercase()));
+        }
+        if let Some(code) = s.strip_prefix("status:") {
+            return code
+                .trim()
+                .parse()
+                .map(MockScenario::HttpStatus)
+                .map_err(|_| format!("Invalid mock HTTP status '{}'", code));
+        }
+
+        match s.to_ascii_lowercase().as_str() {
+            "" | "success" => Ok(MockScenario::Success),
+            "echo" => Ok(MockScenario::Echo),
+            "empty" => Ok(MockScenario::Empty),
+            "no_image" => Ok(MockScenario::NoImage),
+            "timeout" => Ok(MockScenario::Timeout),
+            "no_faces" => Ok(MockScenario::NoFaces),
+            "processing_failed" => Ok(MockScenario::ProcessingFailed),
+            other => Err(format!("Unknown mock scenario '{}'", other)),
+        }
+    }
+}
+
+/// Offline provider that returns canned results without calling any external service.
+pub struct MockProvider {
+    scenario: MockScenario,
+}
+
+impl MockProvider {
+    pub fn new(env: &Env) -> AppResult<Self> {
+        let scenario = match env.var("MOCK_PROVIDER_SCENARIO") {
+            Ok(var) => var.to_string().parse().map_err(AppError::InternalError)?,
+            Err(_) => MockScenario::Success,
+        };
+
+        Ok(Self::with_scenario(scenario))
+    }
+
+    pub fn with_scenario(scenario: MockScenario) -> Self {
+        Self { scenario }
+    }
+
+    fn run_scenario(&self, request: &UnifiedEditRequest) -> AppResult<EditedImage> {
+        match &self.scenario {
+            MockScenario::Success => Ok(EditedImage {
+                data: CANNED_PNG.to_string(),
+                mime_type: "image/png".to_string(),
+            }),
+            MockScenario::Echo => {
+                let image = request
+                    .image
+                    .first()
+                    .ok_or_else(|| AppError::BadRequest("No image provided for editing".to_string()))?;
+                Ok(EditedImage {
+                    data: image.data.clone(),
+                    mime_type: image.mime_type.clone(),
+                })
+            }
+            MockScenario::Empty => Err(AppError::InternalError("No response from Gemini".to_string())),
+            MockScenario::NoImage => Err(AppError::TransformationFailed(
+                "Gemini did not return an image. Try a different photo or emoji.".to_string(),
+            )),
+            MockScenario::Timeout => Err(AppError::GeminiTimeout("Mock provider timed out".to_string())),
+            MockScenario::NoFaces => Err(AppError::NoFacesDetected("Mock provider found no faces".to_string())),
+            MockScenario::ProcessingFailed => Err(AppError::ProcessingFailed("Mock provider failed to process the image".to_string())),
+            MockScenario::FinishReason(reason) => Err(finish_reason_error(reason).unwrap_or_else(|| {
+                AppError::TransformationFailed(
+                    "Gemini did not return an image. Try a different photo or emoji.".to_string(),
+                )
+            })),
+            MockScenario::HttpStatus(status) => Err(status_error(*status, "Mock provider error")),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl ImageEditProvider for MockProvider {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn model_version(&self) -> String {
+        MOCK_MODEL.to_string()
+    }
+
+    fn get_supported_features(&self) -> ProviderFeatures {
+        ProviderFeatures {
+            supports_edit: true,
+        }
+    }
+
+    async fn edit_image(&self, request: &UnifiedEditRequest) -> AppResult<EditResponse> {
+        let images = collect_candidates((0..request.n).map(|_| self.run_scenario(request)))?;
+        Ok(EditResponse { images })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::InputImage;
+    use futures::executor::block_on;
+
+    fn run(scenario: &str) -> AppResult<EditResponse> {
+        run_n(scenario, 1)
+    }
+
+    fn run_n(scenario: &str, n: u32) -> AppResult<EditResponse> {
+        let provider = MockProvider::with_scenario(scenario.parse().unwrap());
+        let request = UnifiedEditRequest {
+            image: vec![InputImage {
+                data: "aW5wdXQ=".to_string(),
+                mime_type: "image/webp".to_string(),
+            }],
+            prompt: "test".to_string(),
+            n,
+        };
+        block_on(provider.edit_image(&request))
+    }
+
+    #[test]
+    fn test_success_returns_canned_png() {
+        let response = run("success").unwrap();
+        assert_eq!(response.images.len(), 1);
+        assert_eq!(response.images[0].data, CANNED_PNG);
+        assert_eq!(response.images[0].mime_type, "image/png");
+    }
+
+    #[test]
+    fn test_returns_one_image_per_candidate() {
+        assert_eq!(run_n("success", 3).unwrap().images.len(), 3);
+        assert!(matches!(run_n("timeout", 3), Err(AppError::GeminiTimeout(_))));
+    }
+
+    #[test]
+    fn test_echo_returns_input() {
+        let response = run("echo").unwrap();
+        assert_eq!(response.images[0].data, "aW5wdXQ=");
+        assert_eq!(response.images[0].mime_type, "image/webp");
+    }
+
+    #[test]
+    fn test_finish_reasons() {
+        assert!(matches!(run("finish:SAFETY"), Err(AppError::GeminiContentFiltered(_))));
+        assert!(matches!(run("finish:PROHIBITED_CONTENT"), Err(AppError::GeminiContentFiltered(_))));
+        assert!(matches!(run("finish:RECITATION"), Err(AppError::TransformationFailed(_))));
+        assert!(matches!(run("finish:OTHER"), Err(AppError::TransformationFailed(_))));
+    }
+
+    #[test]
+    fn test_http_statuses() {
+        assert!(matches!(run("status:400"), Err(AppError::GeminiInvalidRequest(_))));
+        assert!(matches!(run("status:401"), Err(AppError::GeminiApiError(_))));
+        assert!(matches!(run("status:403"), Err(AppError::GeminiApiError(_))));
+        assert!(matches!(run("status:429"), Err(AppError::GeminiQuotaExceeded(_))));
+        assert!(matches!(run("status:503"), Err(AppError::GeminiApiError(_))));
+        assert!(matches!(run("status:418"), Err(AppError::GeminiApiError(_))));
+    }
+
+    #[test]
+    fn test_processing_scenarios() {
+        assert!(matches!(run("empty"), Err(AppError::InternalError(_))));
+        assert!(matches!(run("no_image"), Err(AppError::TransformationFailed(_))));
+        assert!(matches!(run("timeout"), Err(AppError::GeminiTimeout(_))));
+        assert!(matches!(run("no_faces"), Err(AppError::NoFacesDetected(_))));
+        assert!(matches!(run("processing_failed"), Err(AppError::ProcessingFailed(_))));
+    }
+
+    #[test]
+    fn test_invalid_scenarios() {
+        assert!("status:abc".parse::<MockScenario>().is_err());
+        assert!("explode".parse::<MockScenario>().is_err());
+    }
+}
@@ -1 +1,136 @@
-pub mod gemini;
\ No newline at end of file
+pub mod gemini;
+pub mod mock;
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use crate::error::{AppError, AppResult};
+use crate::imaging::DecodedImage;
+use worker::Env;
+
+use gemini::GeminiProvider;
+use mock::MockProvider;
+
+const DEFAULT_PROVIDER: &str = "gemini";
+
+#[derive(Debug, Clone)]
+pub struct ProviderFeatures {
+    pub supports_edit: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct InputImage {
+    /// Base64 encoded image data, without the data URL header.
+    pub data: String,
+    /// MIME type detected from the image content.
+    pub mime_type: String,
+}
+
+impl InputImage {
+    pub fn from_decoded(image: &DecodedImage) -> Self {
+        Self {
+            data: BASE64.encode(&image.bytes),
+            mime_type: image.format.mime_type().to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UnifiedEditRequest {
+    pub image: Vec<InputImage>,
+    pub prompt: String,
+    /// Number of independent candidates to generate.
+    pub n: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct EditedImage {
+    /// Base64 encoded image data as returned by the provider.
+    pub data: String,
+    pub mime_type: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct EditResponse {
+    pub images: Vec<EditedImage>,
+}
+
+#[async_trait(?Send)]
+pub trait ImageEditProvider {
+    fn name(&self) -> &'static str;
+
+    fn model_version(&self) -> String;
+
+    fn get_supported_features(&self) -> ProviderFeatures;
+
+    async fn edit_image(&self, request: &UnifiedEditRequest) -> AppResult<EditResponse>;
+}
+
+/// Selects the image edit provider named by the `IMAGE_PROVIDER` variable
+/// (`gemini` or `mock`), falling back to Gemini when it is not set.
+pub fn get_provider(env: &Env) -> AppResult<Box<dyn ImageEditProvider>> {
+    let name = env
+        .var("IMAGE_PROVIDER")
+        .map(|v| v.to_string())
+        .unwrap_or_else(|_| DEFAULT_PROVIDER.to_string());
+
+    match name.trim().to_ascii_lowercase().as_str() {
+        "gemini" => Ok(Box::new(GeminiProvider::new(env)?)),
+        "mock" => Ok(Box::new(MockProvider::new(env)?)),
+        other => Err(AppError::InternalError(format!(
+            "Unknown IMAGE_PROVIDER '{}'",
+            other
+        ))),
+    }
+}
+
+/// Gathers the outcome of independent candidates, keeping every one that
+/// succeeded. Fails with the first error only when none did.
+pub(crate) fn collect_candidates<T>(results: impl IntoIterator<Item = AppResult<T>>) -> AppResult<Vec<T>> {
+    let mut candidates = Vec::new();
+    let mut first_error = None;
+
+    for result in results {
+        match result {
+            Ok(candidate) => candidates.push(candidate),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    match first_error {
+        Some(e) if candidates.is_empty() => Err(e),
+        None if candidates.is_empty() => Err(AppError::TransformationFailed(
+            "Provider did not return an image".to_string(),
+        )),
+        _ => Ok(candidates),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_candidates_keeps_successes() {
+        let results = vec![
+            Err(AppError::GeminiTimeout("slow".to_string())),
+            Ok(1),
+            Ok(2),
+        ];
+        assert_eq!(collect_candidates(results).unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_collect_candidates_reports_first_error() {
+        let results: Vec<AppResult<u32>> = vec![
+            Err(AppError::GeminiTimeout("slow".to_string())),
+            Err(AppError::NoFacesDetected("none".to_string())),
+        ];
+        assert!(matches!(collect_candidates(results), Err(AppError::GeminiTimeout(_))));
+        assert!(matches!(
+            collect_candidates(Vec::<AppResult<u32>>::new()),
+            Err(AppError::TransformationFailed(_))
+        ));
+    }
+}
@@ -0,0 +1,406 @@
+mod object;
+pub mod policy;
+
+use std::cell::Cell;
+
+use serde::{Deserialize, Serialize};
+use worker::kv::KvStore;
+use worker::{Env, Method, ObjectNamespace, Request, RequestInit, Response, Result};
+
+use crate::auth::ApiKey;
+use crate::error::{AppError, AppResult};
+use policy::{RateLimitPolicy, Usage, WindowMode};
+
+/// Per-client request limiter.
+///
+/// Anonymous callers are limited per IP address under the policy from the
+/// environment; callers with an API key get a daily bucket sized by the key's quota.
+///
+/// Uses the `RATE_LIMITER` Durable Object binding when present so that slot
+/// reservation is atomic, and falls back to the eventually consistent
+/// `RATE_LIMIT_KV` namespace otherwise. With neither binding, requests are not limited.
+pub struct RateLimiter {
+    store: CounterStore,
+    policy: RateLimitPolicy,
+    key: String,
+    /// Wait in milliseconds from the last refused reservation, which a later
+    /// peek can't reconstruct when only a multi-slot request was refused.
+    refused_retry_after_ms: Cell<Option<u64>>,
+}
+
+enum CounterStore {
+    DurableObject(ObjectNamespace),
+    Kv(KvStore),
+    Disabled,
+}
+
+/// Snapshot of a client's quota, reported in `X-RateLimit-*` headers and by `/api/quota`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaStatus {
+    pub limit: u32,
+    pub remaining: u32,
+    /// Unix timestamp (seconds) at which the current window resets.
+    pub reset: u64,
+    /// Seconds until another request would be accepted, when it currently would not.
+    pub retry_after: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum LimiterAction {
+    Reserve,
+    Refund,
+    Peek,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LimiterCall {
+    action: LimiterAction,
+    /// Slots to reserve or refund; ignored by `Peek`.
+    #[serde(default = "one_slot")]
+    count: u32,
+    policy: RateLimitPolicy,
+}
+
+fn one_slot() -> u32 {
+    1
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LimiterReply {
+    accepted: bool,
+    usage: Usage,
+}
+
+impl RateLimiter {
+    pub fn for_request(env: &Env, req: &Request, api_key: Option<&ApiKey>) -> AppResult<Self> {
+        let policy = match api_key {
+            Some(key) => RateLimitPolicy {
+                max_requests: key.record.daily_quota,
+                window: WindowMode::Daily,
+                burst_per_minute: None,
+            },
+            None => RateLimitPolicy::from_env(env)?,
+        };
+
+        let store = if let Ok(namespace) = env.durable_object("RATE_LIMITER") {
+            CounterStore::DurableObject(namespace)
+        } else if let Ok(kv) = env.kv("RATE_LIMIT_KV") {
+            CounterStore::Kv(kv)
+        } else {
+            return Ok(Self {
+                store: CounterStore::Disabled,
+                policy,
+                key: String::new(),
+                refused_retry_after_ms: Cell::new(None),
+            });
+        };
+
+        let client = match api_key {
+            Some(key) => format!("key:{}", key.id),
+            None => client_ip(req).ok_or_else(|| {
+                AppError::InternalError("Unable to determine client IP for rate limiting".to_string())
+            })?,
+        };
+
+        let key = rate_limit_key(&client, &policy.bucket(now_ms()));
+        Ok(Self { store, policy, key, refused_retry_after_ms: Cell::new(None) })
+    }
+
+    /// Takes `count` slots from the client's quota, failing with `RateLimitExceeded`
+    /// when fewer are left. Either every slot is taken or none is.
+    pub async fn reserve(&self, count: u32) -> AppResult<()> {
+        let Some(reply) = self.call(LimiterAction::Reserve, count).await? else {
+            return Ok(());
+        };
+
+        if !reply.accepted {
+            self.refused_retry_after_ms.set(reply.usage.retry_after_ms);
+            let message = if reply.usage.allowed {
+                self.policy.insufficient_message(count, &reply.usage)
+            } else {
+                self.policy.exceeded_message(&reply.usage)
+            };
+            return Err(AppError::RateLimitExceeded(message));
+        }
+
+        Ok(())
+    }
+
+    /// Returns `count` slots taken by [`RateLimiter::reserve`] whose work failed.
+    pub async fn refund(&self, count: u32) -> AppResult<()> {
+        if count > 0 {
+            self.call(LimiterAction::Refund, count).await?;
+        }
+        Ok(())
+    }
+
+    /// Reads the client's current quota without consuming it, or `None` when limiting is disabled.
+    pub async fn quota(&self) -> AppResult<Option<QuotaStatus>> {
+        let reply = self.call(LimiterAction::Peek, 0).await?;
+        Ok(reply.map(|mut reply| {
+            reply.usage.retry_after_ms = reply.usage.retry_after_ms.or(self.refused_retry_after_ms.get());
+            QuotaStatus::from_usage(&self.policy, &reply.usage)
+        }))
+    }
+
+    async fn call(&self, action: LimiterAction, count: u32) -> AppResult<Option<LimiterReply>> {
+        let reply = match &self.store {
+            CounterStore::DurableObject(namespace) => {
+                call_limiter_object(namespace, &self.key, action, count, &self.policy).await?
+            }
+            CounterStore::Kv(kv) => {
+                let mut timestamps = read_kv_timestamps(kv, &self.key).await;
+                let reply = apply_action(&self.policy, &mut timestamps, action, count, now_ms());
+                if action != LimiterAction::Peek {
+                    write_kv_timestamps(kv, &self.key, &timestamps, &self.policy).await?;
+                }
+                reply
+            }
+            CounterStore::Disabled => return Ok(None),
+        };
+
+        Ok(Some(reply))
+    }
+}
+
+impl QuotaStatus {
+    fn from_usage(policy: &RateLimitPolicy, usage: &Usage) -> Self {
+        Self {
+            limit: policy.max_requests,
+            remaining: policy.max_requests.saturating_sub(usage.count),
+            reset: usage.reset_ms.div_ceil(1000),
+            retry_after: usage.retry_after_ms.map(|ms| ms.div_ceil(1000)),
+        }
+    }
+
+    /// Adds the `X-RateLimit-*` headers, plus `Retry-After` when the response is a 429.
+    pub fn apply_headers(&self, response: &mut Response) -> Result<()> {
+        let status = response.status_code();
+        let headers = response.headers_mut();
+        headers.set("X-RateLimit-Limit", &self.limit.to_string())?;
+        headers.set("X-RateLimit-Remaining", &self.remaining.to_string())?;
+        headers.set("X-RateLimit-Reset", &self.reset.to_string())?;
+
+        if status == 429 {
+            headers.set("Retry-After", &self.retry_after_secs(now_ms() / 1000).to_string())?;
+        }
+
+        Ok(())
+    }
+
+    /// Seconds a refused client should wait. Refusals that don't come from this
+    /// quota, like the provider's own, or requests that can never fit, wait for the window to reset.
+    fn retry_after_secs(&self, now_secs: u64) -> u64 {
+        self.retry_after.unwrap_or_else(|| self.reset.saturating_sub(now_secs))
+    }
+}
+
+/// Applies `action` for `count` slots to a client's stored request timestamps.
+///
+/// Shared by the Durable Object and the KV fallback so both enforce the policy identically.
+fn apply_action(
+    policy: &RateLimitPolicy,
+    timestamps: &mut Vec<u64>,
+    action: LimiterAction,
+    count: u32,
+    now_ms: u64,
+) -> LimiterReply {
+    policy.prune(timestamps, now_ms);
+
+    let accepted = match action {
+        LimiterAction::Reserve => {
+            let before = timestamps.len();
+            for _ in 0..count {
+                if !policy.evaluate(timestamps, now_ms).allowed {
+                    break;
+                }
+                timestamps.push(now_ms);
+            }
+            let reserved = timestamps.len() - before == count as usize;
+            if !reserved {
+                timestamps.truncate(before);
+                let mut usage = policy.evaluate(timestamps, now_ms);
+                usage.retry_after_ms = policy.retry_after_for(timestamps, count, now_ms);
+                return LimiterReply { accepted: false, usage };
+            }
+            true
+        }
+        LimiterAction::Refund => {
+            for _ in 0..count {
+                if let Some(latest) = timestamps.iter().copied().enumerate().max_by_key(|&(_, t)| t) {
+                    timestamps.remove(latest.0);
+                }
+            }
+            true
+        }
+        LimiterAction::Peek => true,
+    };
+
+    LimiterReply {
+        accepted,
+        usage: policy.evaluate(timestamps, now_ms),
+    }
+}
+
+async fn call_limiter_object(
+    namespace: &ObjectNamespace,
+    key: &str,
+    action: LimiterAction,
+    count: u32,
+    policy: &RateLimitPolicy,
+) -> AppResult<LimiterReply> {
+    let stub = namespace.id_from_name(key)?.get_stub()?;
+
+    let body = serde_json::to_string(&LimiterCall {
+        action,
+        count,
+        policy: policy.clone(),
+    })?;
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post)
+        .with_body(Some(worker::wasm_bindgen::JsValue::from_str(&body)));
+    let request = Request::new_with_init("https://rate-limiter/", &init)?;
+
+    let mut response = stub.fetch_with_request(request).await?;
+    if response.status_code() != 200 {
+        return Err(AppError::InternalError(format!(
+            "Rate limiter returned status {}",
+            response.status_code()
+        )));
+    }
+
+    Ok(response.json().await?)
+}
+
+async fn read_kv_timestamps(kv: &KvStore, key: &str) -> Vec<u64> {
+    match kv.get(key).text().await {
+        Ok(Some(value)) => serde_json::from_str(&value).unwrap_or_default(),
+        Ok(None) => Vec::new(),
+        Err(_) => Vec::new(),
+    }
+}
+
+async fn write_kv_timestamps(
+    kv: &KvStore,
+    key: &str,
+    timestamps: &[u64],
+    policy: &RateLimitPolicy,
+) -> AppResult<()> {
+    kv.put(key, serde_json::to_string(timestamps)?)?
+        .expiration_ttl(retention_secs(policy))
+        .execute()
+        .await?;
+    Ok(())
+}
+
+// Stored history outlives the policy window by a day so late refunds still find it.
+fn retention_secs(policy: &RateLimitPolicy) -> u64 {
+    (policy.retention_ms() + policy::DAY_MILLIS) / 1000
+}
+
+fn client_ip(req: &Request) -> Option<String> {
+    ["CF-Connecting-IP", "X-Forwarded-For", "X-Real-IP"]
+        .iter()
+        .find_map(|name| req.headers().get(name).ok().flatten())
+}
+
+fn rate_limit_key(client: &str, bucket: &str) -> String {
+    format!("rate_limit:{}:{}", client, bucket)
+}
+
+fn now_ms() -> u64 {
+    worker::Date::now().as_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2025-01-31T12:00:00Z
+    const NOON: u64 = 1_738_324_800_000;
+
+    #[test]
+    fn test_reserve_until_exhausted_then_refund() {
+        let policy = RateLimitPolicy {
+            max_requests: 2,
+            ..RateLimitPolicy::default()
+        };
+        let mut timestamps = Vec::new();
+
+        assert!(apply_action(&policy, &mut timestamps, LimiterAction::Reserve, 1, NOON).accepted);
+        assert!(apply_action(&policy, &mut timestamps, LimiterAction::Reserve, 1, NOON + 1).accepted);
+        let rejected = apply_action(&policy, &mut timestamps, LimiterAction::Reserve, 1, NOON + 2);
+        assert!(!rejected.accepted);
+        assert_eq!(rejected.usage.count, 2);
+
+        let refunded = apply_action(&policy, &mut timestamps, LimiterAction::Refund, 1, NOON + 3);
+        assert_eq!(refunded.usage.count, 1);
+        assert_eq!(timestamps, vec![NOON]);
+    }
+
+    #[test]
+    fn test_reserve_many_is_all_or_nothing() {
+        let policy = RateLimitPolicy {
+            max_requests: 3,
+            ..RateLimitPolicy::default()
+        };
+        let mut timestamps = vec![NOON];
+
+        let rejected = apply_action(&policy, &mut timestamps, LimiterAction::Reserve, 3, NOON + 1);
+        assert!(!rejected.accepted);
+        assert!(rejected.usage.allowed);
+        assert_eq!(timestamps, vec![NOON]);
+        // Three fit again once the daily window resets at 2025-02-01T00:00:00Z
+        assert_eq!(rejected.usage.retry_after_ms, Some(1_738_368_000_000 - NOON - 1));
+
+        assert!(apply_action(&policy, &mut timestamps, LimiterAction::Reserve, 2, NOON + 2).accepted);
+        assert_eq!(timestamps.len(), 3);
+
+        let refunded = apply_action(&policy, &mut timestamps, LimiterAction::Refund, 2, NOON + 3);
+        assert_eq!(refunded.usage.count, 1);
+        assert_eq!(timestamps, vec![NOON]);
+    }
+
+    #[test]
+    fn test_call_count_defaults_to_one() {
+        let call: LimiterCall = serde_json::from_value(serde_json::json!({
+            "action": "reserve",
+            "policy": RateLimitPolicy::default(),
+        }))
+        .unwrap();
+        assert_eq!(call.count, 1);
+    }
+
+    #[test]
+    fn test_peek_does_not_consume() {
+        let policy = RateLimitPolicy::default();
+        let mut timestamps = vec![NOON];
+        let reply = apply_action(&policy, &mut timestamps, LimiterAction::Peek, 0, NOON + 1);
+        assert_eq!(reply.usage.count, 1);
+        assert_eq!(timestamps, vec![NOON]);
+    }
+
+    #[test]
+    fn test_quota_status_from_usage() {
+        let policy = RateLimitPolicy::default();
+        let usage = policy.evaluate(&[NOON, NOON], NOON);
+        let status = QuotaStatus::from_usage(&policy, &usage);
+        assert_eq!(status.limit, policy.max_requests);
+        assert_eq!(status.remaining, policy.max_requests - 2);
+        // 2025-02-01T00:00:00Z
+        assert_eq!(status.reset, 1_738_368_000);
+        assert_eq!(status.retry_after, None);
+        // Refusals the quota doesn't explain wait for the reset
+        assert_eq!(status.retry_after_secs(NOON / 1000), 12 * 60 * 60);
+        assert_eq!(QuotaStatus { retry_after: Some(30), ..status }.retry_after_secs(NOON / 1000), 30);
+    }
+
+    #[test]
+    fn test_rate_limit_key_scheme() {
+        assert_eq!(
+            rate_limit_key("203.0.113.7", "2025-01-31"),
+            "rate_limit:203.0.113.7:2025-01-31"
+        );
+    }
+}
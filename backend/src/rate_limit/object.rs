@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use worker::{durable_object, Env, Request, Response, Result, State};
+
+use super::{apply_action, now_ms, retention_secs, LimiterAction, LimiterCall};
+
+const TIMESTAMPS_KEY: &str = "timestamps";
+
+/// Durable Object holding one client's request history for a rate limit bucket.
+///
+/// Each rate limit key maps to its own instance, and the runtime delivers
+/// requests to an instance one at a time, so the read-check-write for a
+/// reservation cannot race with a concurrent request from the same client.
+#[durable_object]
+pub struct RateLimiterObject {
+    state: State,
+}
+
+impl DurableObject for RateLimiterObject {
+    fn new(state: State, _env: Env) -> Self {
+        Self { state }
+    }
+
+    async fn fetch(&self, mut req: Request) -> Result<Response> {
+        let call: LimiterCall = req.json().await?;
+        let storage = self.state.storage();
+        let mut timestamps: Vec<u64> = storage.get(TIMESTAMPS_KEY).await.unwrap_or_default();
+
+        let reply = apply_action(&call.policy, &mut timestamps, call.action, call.count, now_ms());
+
+        if call.action != LimiterAction::Peek {
+            storage.put(TIMESTAMPS_KEY, &timestamps).await?;
+            // Clear the history once nothing in it can affect a decision anymore
+            storage
+                .set_alarm(Duration::from_secs(retention_secs(&call.policy)))
+                .await?;
+        }
+
+        Response::from_json(&reply)
+    }
+
+    async fn alarm(&self) -> Result<Response> {
+        self.state.storage().delete_all().await?;
+        Response::ok("")
+    }
+}
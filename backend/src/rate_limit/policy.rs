@@ -0,0 +1,427 @@
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use worker::Env;
+
+use crate::error::{AppError, AppResult};
+
+pub const DEFAULT_MAX_REQUESTS: u32 = 5;
+pub const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+const MINUTE_MILLIS: u64 = 60 * 1000;
+
+/// How the main request window is measured.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowMode {
+    /// Calendar day in UTC, resetting at midnight.
+    Daily,
+    /// Sliding window of the given length ending at the current time.
+    Rolling { seconds: u64 },
+}
+
+/// Rate limit settings, loaded from the `RATE_LIMIT_*` environment variables:
+///
+/// - `RATE_LIMIT_MAX_REQUESTS`: requests allowed per window (default 5)
+/// - `RATE_LIMIT_WINDOW`: `daily` (default) or `rolling`
+/// - `RATE_LIMIT_WINDOW_SECONDS`: rolling window length (default 86400)
+/// - `RATE_LIMIT_BURST_PER_MINUTE`: optional cap on requests in any 60 seconds
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimitPolicy {
+    pub max_requests: u32,
+    pub window: WindowMode,
+    pub burst_per_minute: Option<u32>,
+}
+
+/// Result of checking a client's request history against a policy.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    pub allowed: bool,
+    /// Requests counted in the current window.
+    pub count: u32,
+    /// Unix timestamp (milliseconds) when the current window resets.
+    pub reset_ms: u64,
+    /// Milliseconds until another request would be accepted, when it currently would not.
+    pub retry_after_ms: Option<u64>,
+    pub burst_exceeded: bool,
+}
+
+impl Default for RateLimitPolicy {
+    fn default() -> Self {
+        Self {
+            max_requests: DEFAULT_MAX_REQUESTS,
+            window: WindowMode::Daily,
+            burst_per_minute: None,
+        }
+    }
+}
+
+impl RateLimitPolicy {
+    pub fn from_env(env: &Env) -> AppResult<Self> {
+        Self::from_vars(|name| env.var(name).ok().map(|v| v.to_string()))
+            .map_err(|e| AppError::InternalError(format!("Invalid rate limit configuration: {}", e)))
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let parse_u32 = |name: &str| -> Result<Option<u32>, String> {
+            var(name)
+                .map(|v| v.trim().parse().map_err(|_| format!("{} must be a non-negative integer", name)))
+                .transpose()
+        };
+
+        let max_requests = parse_u32("RATE_LIMIT_MAX_REQUESTS")?.unwrap_or(DEFAULT_MAX_REQUESTS);
+
+        let window = match var("RATE_LIMIT_WINDOW").as_deref().map(str::trim) {
+            None | Some("") | Some("daily") => WindowMode::Daily,
+            Some("rolling") => {
+                let seconds = match var("RATE_LIMIT_WINDOW_SECONDS") {
+                    Some(v) => v
+                        .trim()
+                        .parse()
+                        .map_err(|_| "RATE_LIMIT_WINDOW_SECONDS must be a positive integer".to_string())?,
+                    None => DAY_MILLIS / 1000,
+                };
+                if seconds == 0 {
+                    return Err("RATE_LIMIT_WINDOW_SECONDS must be a positive integer".to_string());
+                }
+                WindowMode::Rolling { seconds }
+            }
+            Some(other) => return Err(format!("unknown RATE_LIMIT_WINDOW '{}'", other)),
+        };
+
+        let burst_per_minute = parse_u32("RATE_LIMIT_BURST_PER_MINUTE")?.filter(|&n| n > 0);
+
+        Ok(Self {
+            max_requests,
+            window,
+            burst_per_minute,
+        })
+    }
+
+    /// Suffix distinguishing counters of different windows for the same client.
+    pub fn bucket(&self, now_ms: u64) -> String {
+        match self.window {
+            WindowMode::Daily => utc_date(now_ms),
+            WindowMode::Rolling { .. } => "rolling".to_string(),
+        }
+    }
+
+    /// How long a request timestamp can still affect a decision.
+    pub fn retention_ms(&self) -> u64 {
+        let window_ms = match self.window {
+            WindowMode::Daily => DAY_MILLIS,
+            WindowMode::Rolling { seconds } => seconds * 1000,
+        };
+        window_ms.max(MINUTE_MILLIS)
+    }
+
+    fn window_start(&self, now_ms: u64) -> u64 {
+        match self.window {
+            WindowMode::Daily => now_ms - now_ms % DAY_MILLIS,
+            WindowMode::Rolling { seconds } => now_ms.saturating_sub(seconds * 1000),
+        }
+    }
+
+    /// Drops timestamps that no longer count towards either the window or the burst limit.
+    pub fn prune(&self, timestamps: &mut Vec<u64>, now_ms: u64) {
+        let keep_from = self
+            .window_start(now_ms)
+            .min(now_ms.saturating_sub(MINUTE_MILLIS));
+        timestamps.retain(|&t| t >= keep_from);
+    }
+
+    /// Checks whether one more request may be made given the client's request history.
+    pub fn evaluate(&self, timestamps: &[u64], now_ms: u64) -> Usage {
+        let window_start = self.window_start(now_ms);
+        let in_window: Vec<u64> = timestamps.iter().copied().filter(|&t| t >= window_start).collect();
+        let count = in_window.len() as u32;
+
+        let reset_ms = match self.window {
+            WindowMode::Daily => window_start + DAY_MILLIS,
+            WindowMode::Rolling { seconds } => {
+                in_window.iter().min().copied().unwrap_or(now_ms) + seconds * 1000
+            }
+        };
+
+        let window_exceeded = count >= self.max_requests;
+
+        let burst_start = now_ms.saturating_sub(MINUTE_MILLIS);
+        let burst: Vec<u64> = timestamps.iter().copied().filter(|&t| t >= burst_start).collect();
+        let burst_exceeded = self
+            .burst_per_minute
+            .is_some_and(|limit| burst.len() as u32 >= limit);
+
+        let retry_after_ms = if window_exceeded {
+            Some(reset_ms.saturating_sub(now_ms))
+        } else if burst_exceeded {
+            burst
+                .iter()
+                .min()
+                .map(|&oldest| (oldest + MINUTE_MILLIS).saturating_sub(now_ms))
+        } else {
+            None
+        };
+
+        Usage {
+            allowed: !window_exceeded && !burst_exceeded,
+            count,
+            reset_ms,
+            retry_after_ms,
+            burst_exceeded: burst_exceeded && !window_exceeded,
+        }
+    }
+
+    /// Milliseconds until `requested` more requests would all be accepted, or
+    /// `None` when they never will because that is more than the policy allows at once.
+    pub fn retry_after_for(&self, timestamps: &[u64], requested: u32, now_ms: u64) -> Option<u64> {
+        // Time until enough of the oldest requests inside `from`.. expire, `length` after being made
+        let wait = |from: u64, length: u64, limit: u32| -> Option<u64> {
+            if requested > limit {
+                return None;
+            }
+            let mut recent: Vec<u64> = timestamps.iter().copied().filter(|&t| t >= from).collect();
+            let excess = (recent.len() + requested as usize).saturating_sub(limit as usize);
+            if excess == 0 {
+                return Some(0);
+            }
+            recent.sort_unstable();
+            Some((recent[excess - 1] + length).saturating_sub(now_ms))
+        };
+
+        let window_start = self.window_start(now_ms);
+        let window = match self.window {
+            // Nothing leaves a calendar day before it ends
+            WindowMode::Daily if requested > self.max_requests => None,
+            WindowMode::Daily => Some(match wait(window_start, DAY_MILLIS, self.max_requests)? {
+                0 => 0,
+                _ => window_start + DAY_MILLIS - now_ms,
+            }),
+            WindowMode::Rolling { seconds } => wait(window_start, seconds * 1000, self.max_requests),
+        }?;
+        let burst = match self.burst_per_minute {
+            Some(limit) => wait(now_ms.saturating_sub(MINUTE_MILLIS), MINUTE_MILLIS, limit)?,
+            None => 0,
+        };
+        Some(window.max(burst))
+    }
+
+    pub fn exceeded_message(&self, usage: &Usage) -> String {
+        if usage.burst_exceeded {
+            return format!(
+                "Too many requests. You can make {} requests per minute. Please slow down.",
+                self.burst_per_minute.unwrap_or_default()
+            );
+        }
+
+        match self.window {
+            WindowMode::Daily => format!(
+                "Rate limit exceeded. You can make {} requests per day. Try again tomorrow.",
+                self.max_requests
+            ),
+            WindowMode::Rolling { seconds } => format!(
+                "Rate limit exceeded. You can make {} requests every {}. Try again later.",
+                self.max_requests,
+                describe_duration(seconds)
+            ),
+        }
+    }
+
+    /// Message for a multi-slot reservation that does not fit in what is left of the quota.
+    pub fn insufficient_message(&self, requested: u32, usage: &Usage) -> String {
+        let remaining = self.max_requests.saturating_sub(usage.count);
+        match self.burst_per_minute {
+            Some(burst) if requested > burst => format!(
+                "This request needs {} requests but you can make at most {} per minute.",
+                requested, burst
+            ),
+            _ if requested > remaining => format!(
+                "This request needs {} requests but only {} remain in your quota.",
+                requested, remaining
+            ),
+            _ => format!(
+                "This request needs {} requests, which would exceed the per-minute limit. Please slow down.",
+                requested
+            ),
+        }
+    }
+}
+
+fn utc_date(now_ms: u64) -> String {
+    DateTime::from_timestamp_millis(now_ms as i64)
+        .map(|dt| dt.format("%Y-%m-%d").to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn describe_duration(seconds: u64) -> String {
+    match seconds {
+        s if s % 86_400 == 0 => plural(s / 86_400, "day"),
+        s if s % 3_600 == 0 => plural(s / 3_600, "hour"),
+        s if s % 60 == 0 => plural(s / 60, "minute"),
+        s => plural(s, "second"),
+    }
+}
+
+fn plural(n: u64, unit: &str) -> String {
+    if n == 1 {
+        unit.to_string()
+    } else {
+        format!("{} {}s", n, unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    // 2025-01-31T12:00:00Z
+    const NOON: u64 = 1_738_324_800_000;
+    // 2025-02-01T00:00:00Z
+    const NEXT_MIDNIGHT: u64 = 1_738_368_000_000;
+
+    fn policy_from(vars: &[(&str, &str)]) -> Result<RateLimitPolicy, String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        RateLimitPolicy::from_vars(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(policy_from(&[]).unwrap(), RateLimitPolicy::default());
+    }
+
+    #[test]
+    fn test_from_vars() {
+        let policy = policy_from(&[
+            ("RATE_LIMIT_MAX_REQUESTS", "50"),
+            ("RATE_LIMIT_WINDOW", "rolling"),
+            ("RATE_LIMIT_WINDOW_SECONDS", "3600"),
+            ("RATE_LIMIT_BURST_PER_MINUTE", "3"),
+        ])
+        .unwrap();
+        assert_eq!(policy.max_requests, 50);
+        assert_eq!(policy.window, WindowMode::Rolling { seconds: 3600 });
+        assert_eq!(policy.burst_per_minute, Some(3));
+    }
+
+    #[test]
+    fn test_invalid_vars() {
+        assert!(policy_from(&[("RATE_LIMIT_MAX_REQUESTS", "-1")]).is_err());
+        assert!(policy_from(&[("RATE_LIMIT_WINDOW", "weekly")]).is_err());
+        assert!(policy_from(&[("RATE_LIMIT_WINDOW", "rolling"), ("RATE_LIMIT_WINDOW_SECONDS", "0")]).is_err());
+    }
+
+    #[test]
+    fn test_daily_bucket_is_utc_date() {
+        let policy = RateLimitPolicy::default();
+        assert_eq!(policy.bucket(NOON), "2025-01-31");
+        assert_eq!(policy.bucket(NEXT_MIDNIGHT - 1), "2025-01-31");
+        assert_eq!(policy.bucket(NEXT_MIDNIGHT), "2025-02-01");
+    }
+
+    #[test]
+    fn test_daily_window_resets_at_utc_midnight() {
+        let policy = RateLimitPolicy::default();
+        let usage = policy.evaluate(&[NOON - 1000], NOON);
+        assert!(usage.allowed);
+        assert_eq!(usage.count, 1);
+        assert_eq!(usage.reset_ms, NEXT_MIDNIGHT);
+
+        // Requests from yesterday don't count
+        let yesterday = vec![NOON - DAY_MILLIS; 5];
+        assert_eq!(policy.evaluate(&yesterday, NOON).count, 0);
+    }
+
+    #[test]
+    fn test_daily_limit_exhausted() {
+        let policy = RateLimitPolicy::default();
+        let timestamps = vec![NOON - 60 * MINUTE_MILLIS; 5];
+        let usage = policy.evaluate(&timestamps, NOON);
+        assert!(!usage.allowed);
+        assert!(!usage.burst_exceeded);
+        assert_eq!(usage.retry_after_ms, Some(NEXT_MIDNIGHT - NOON));
+    }
+
+    #[test]
+    fn test_rolling_window() {
+        let policy = RateLimitPolicy {
+            max_requests: 2,
+            window: WindowMode::Rolling { seconds: 3600 },
+            burst_per_minute: None,
+        };
+        let oldest = NOON - 30 * MINUTE_MILLIS;
+        let usage = policy.evaluate(&[oldest, NOON - 10 * MINUTE_MILLIS], NOON);
+        assert!(!usage.allowed);
+        assert_eq!(usage.reset_ms, oldest + 3_600_000);
+        assert_eq!(usage.retry_after_ms, Some(30 * MINUTE_MILLIS));
+
+        // The older request has slid out of the window
+        assert!(policy.evaluate(&[NOON - 61 * MINUTE_MILLIS, NOON - 10 * MINUTE_MILLIS], NOON).allowed);
+    }
+
+    #[test]
+    fn test_burst_limit() {
+        let policy = RateLimitPolicy {
+            burst_per_minute: Some(2),
+            ..RateLimitPolicy::default()
+        };
+        let usage = policy.evaluate(&[NOON - 40_000, NOON - 10_000], NOON);
+        assert!(!usage.allowed);
+        assert!(usage.burst_exceeded);
+        assert_eq!(usage.retry_after_ms, Some(20_000));
+
+        assert!(policy.evaluate(&[NOON - 70_000, NOON - 10_000], NOON).allowed);
+    }
+
+    #[test]
+    fn test_retry_after_for_several_requests() {
+        let rolling = RateLimitPolicy {
+            max_requests: 3,
+            window: WindowMode::Rolling { seconds: 3600 },
+            burst_per_minute: Some(2),
+        };
+        let timestamps = [NOON - 50 * MINUTE_MILLIS, NOON - 20 * MINUTE_MILLIS];
+        assert_eq!(rolling.retry_after_for(&timestamps, 1, NOON), Some(0));
+        // Two more need the oldest request to leave the window
+        assert_eq!(rolling.retry_after_for(&timestamps, 2, NOON), Some(10 * MINUTE_MILLIS));
+        // The burst limit can't take three at once, nor the window four
+        assert_eq!(rolling.retry_after_for(&[], 3, NOON), None);
+        assert_eq!(rolling.retry_after_for(&[NOON - 10_000, NOON - 5_000], 1, NOON), Some(50_000));
+
+        let daily = RateLimitPolicy::default();
+        let used = vec![NOON - 60 * MINUTE_MILLIS; 3];
+        assert_eq!(daily.retry_after_for(&used, 2, NOON), Some(0));
+        assert_eq!(daily.retry_after_for(&used, 3, NOON), Some(NEXT_MIDNIGHT - NOON));
+        assert_eq!(daily.retry_after_for(&used, 6, NOON), None);
+    }
+
+    #[test]
+    fn test_prune_keeps_burst_window() {
+        let policy = RateLimitPolicy::default();
+        // Shortly after midnight the burst window reaches into the previous day
+        let now = NEXT_MIDNIGHT + 10_000;
+        let mut timestamps = vec![NOON, NEXT_MIDNIGHT - 20_000, NEXT_MIDNIGHT + 5_000];
+        policy.prune(&mut timestamps, now);
+        assert_eq!(timestamps, vec![NEXT_MIDNIGHT - 20_000, NEXT_MIDNIGHT + 5_000]);
+    }
+
+    #[test]
+    fn test_exceeded_messages() {
+        let rolling = RateLimitPolicy {
+            max_requests: 10,
+            window: WindowMode::Rolling { seconds: 7200 },
+            burst_per_minute: None,
+        };
+        let usage = rolling.evaluate(&[], NOON);
+        assert_eq!(
+            rolling.exceeded_message(&usage),
+            "Rate limit exceeded. You can make 10 requests every 2 hours. Try again later."
+        );
+
+        let usage = rolling.evaluate(&[NOON; 8], NOON);
+        assert_eq!(
+            rolling.insufficient_message(3, &usage),
+            "This request needs 3 requests but only 2 remain in your quota."
+        );
+    }
+}
@@ -0,0 +1,320 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use futures::{Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use worker::{FormEntry, Request};
+
+use crate::error::{AppError, AppResult};
+use crate::models::{AnimateRequest, ImageUpload, TransformRequest};
+
+/// Room in a body for everything besides the image: the other fields, JSON
+/// syntax and multipart boundaries.
+const BODY_OVERHEAD_BYTES: usize = 64 * 1024;
+
+/// How the body of a `/api/transform` request is encoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum UploadKind {
+    /// JSON `TransformRequest` with the image as a base64 data URL.
+    Json,
+    /// `multipart/form-data` with an `image` file part and the other fields alongside.
+    Multipart,
+    /// Raw image bytes, with the other fields in the query string.
+    Binary(String),
+}
+
+fn upload_kind(content_type: Option<&str>) -> AppResult<UploadKind> {
+    let mime = content_type
+        .and_then(|ct| ct.split(';').next())
+        .map(|mime| mime.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    match mime.as_str() {
+        "" | "application/json" => Ok(UploadKind::Json),
+        "multipart/form-data" => Ok(UploadKind::Multipart),
+        m if m.starts_with("image/") => Ok(UploadKind::Binary(mime)),
+        other => Err(AppError::BadRequest(format!(
+            "Unsupported Content-Type '{}'. Use application/json, multipart/form-data or an image/* body",
+            other
+        ))),
+    }
+}
+
+/// Reads a `TransformRequest` from a JSON, multipart or raw image body.
+///
+/// Bodies too big to hold an image of `max_image_bytes` are refused up front
+/// when their Content-Length says so. JSON and raw bodies without one are read
+/// chunk by chunk and refused as soon as they pass the limit. Multipart forms
+/// are parsed whole by the runtime, so a chunked form is only checked once it
+/// has been buffered. Multipart and raw uploads keep their bytes as they are,
+/// so a large photo is only ever held in memory once before it is decoded.
+pub async fn read_transform_request(req: &mut Request, max_image_bytes: usize) -> AppResult<TransformRequest> {
+    read_request(req, max_image_bytes, |image, field| {
+        Ok(TransformRequest {
+            image,
+            emoji: field("emoji").unwrap_or_default(),
+            description: field("description"),
+            output_format: parse_option("output_format", field("output_format"))?,
+            quality: parse_option("quality", field("quality"))?,
+            max_dimension: parse_option("max_dimension", field("max_dimension"))?,
+            n: parse_option("n", field("n"))?,
+            intensity: parse_option("intensity", field("intensity"))?,
+            subject: parse_option("subject", field("subject"))?,
+            subjects: parse_json_list("subjects", field("subjects"))?,
+            composite: parse_option("composite", field("composite"))?,
+            composite_padding: parse_option("composite_padding", field("composite_padding"))?,
+        })
+    })
+    .await
+}
+
+/// Reads an `AnimateRequest` the same ways as [`read_transform_request`].
+pub async fn read_animate_request(req: &mut Request, max_image_bytes: usize) -> AppResult<AnimateRequest> {
+    read_request(req, max_image_bytes, |image, field| {
+        Ok(AnimateRequest {
+            image,
+            emoji: field("emoji").unwrap_or_default(),
+            description: field("description"),
+            subject: parse_option("subject", field("subject"))?,
+            frames: parse_option("frames", field("frames"))?,
+            delay_ms: parse_option("delay_ms", field("delay_ms"))?,
+            loops: parse_option("loops", field("loops"))?,
+            output_format: parse_option("output_format", field("output_format"))?,
+            max_dimension: parse_option("max_dimension", field("max_dimension"))?,
+        })
+    })
+    .await
+}
+
+/// Deserializes a JSON body, or hands the image of a multipart or raw upload
+/// to `from_fields` along with a lookup for its other fields, which come from
+/// the form or the query string respectively.
+async fn read_request<T: DeserializeOwned>(
+    req: &mut Request,
+    max_image_bytes: usize,
+    from_fields: impl FnOnce(ImageUpload, &dyn Fn(&str) -> Option<String>) -> AppResult<T>,
+) -> AppResult<T> {
+    let content_type = req.headers().get("Content-Type")?;
+    let kind = upload_kind(content_type.as_deref())?;
+
+    let content_length = req
+        .headers()
+        .get("Content-Length")?
+        .and_then(|length| length.trim().parse::<usize>().ok());
+    if content_length.is_some_and(|length| length > max_body_bytes(&kind, max_image_bytes)) {
+        return Err(image_too_large(max_image_bytes));
+    }
+
+    match kind {
+        UploadKind::Json => {
+            let body = read_body(req, max_body_bytes(&kind, max_image_bytes), max_image_bytes).await?;
+            serde_json::from_slice(&body)
+                .map_err(|e| AppError::BadRequest(format!("Invalid JSON in request body: {}", e)))
+        }
+        UploadKind::Multipart => {
+            let form = req
+                .form_data()
+                .await
+                .map_err(|e| AppError::BadRequest(format!("Invalid multipart body: {}", e)))?;
+
+            let image = match form.get("image") {
+                Some(FormEntry::File(file)) => {
+                    if file.size() > max_image_bytes {
+                        return Err(image_too_large(max_image_bytes));
+                    }
+                    ImageUpload::Bytes { mime_type: file.type_(), bytes: file.bytes().await? }
+                }
+                Some(FormEntry::Field(value)) => ImageUpload::DataUrl(value),
+                None => ImageUpload::default(),
+            };
+
+            from_fields(image, &|name| form.get_field(name))
+        }
+        UploadKind::Binary(mime_type) => {
+            let url = req.url()?;
+            let query = |name: &str| {
+                url.query_pairs()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.into_owned())
+            };
+            let bytes = read_body(req, max_image_bytes, max_image_bytes).await?;
+
+            from_fields(ImageUpload::Bytes { mime_type, bytes }, &query)
+        }
+    }
+}
+
+/// Reads a body of at most `limit` bytes, refusing it as soon as it grows past
+/// that so a chunked upload is never held whole.
+async fn read_body(req: &mut Request, limit: usize, max_image_bytes: usize) -> AppResult<Vec<u8>> {
+    if req.inner().body().is_none() {
+        return Ok(Vec::new());
+    }
+    read_bounded(req.stream()?, limit)
+        .await?
+        .ok_or_else(|| image_too_large(max_image_bytes))
+}
+
+/// Collects `body`, or `None` once it has more than `limit` bytes.
+async fn read_bounded(body: impl Stream<Item = worker::Result<Vec<u8>>>, limit: usize) -> AppResult<Option<Vec<u8>>> {
+    let mut body = std::pin::pin!(body);
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk?;
+        if bytes.len() + chunk.len() > limit {
+            return Ok(None);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(Some(bytes))
+}
+
+/// Largest body that can hold an image of `max_image_bytes`.
+fn max_body_bytes(kind: &UploadKind, max_image_bytes: usize) -> usize {
+    match kind {
+        // Base64 takes four characters for every three bytes
+        UploadKind::Json => max_image_bytes.div_ceil(3) * 4 + BODY_OVERHEAD_BYTES,
+        UploadKind::Multipart => max_image_bytes + BODY_OVERHEAD_BYTES,
+        UploadKind::Binary(_) => max_image_bytes,
+    }
+}
+
+pub fn image_too_large(max_image_bytes: usize) -> AppError {
+    AppError::ImageTooLarge(format!("Image is too large (max {})", format_size(max_image_bytes)))
+}
+
+fn format_size(bytes: usize) -> String {
+    if bytes >= 1024 * 1024 && bytes.is_multiple_of(1024 * 1024) {
+        format!("{}MB", bytes / (1024 * 1024))
+    } else if bytes >= 1024 {
+        format!("{}KB", bytes / 1024)
+    } else {
+        format!("{} bytes", bytes)
+    }
+}
+
+/// Parses an optional form field or query parameter; blank values count as absent.
+fn parse_option<T>(name: &str, value: Option<String>) -> AppResult<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match value.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|e| AppError::BadRequest(format!("Invalid {} '{}': {}", name, value, e))),
+    }
+}
+
+/// Parses a list passed as JSON text in a form field or query parameter;
+/// blank values count as an empty list.
+fn parse_json_list<T: DeserializeOwned>(name: &str, value: Option<String>) -> AppResult<Vec<T>> {
+    match value.as_deref().map(str::trim) {
+        None | Some("") => Ok(Vec::new()),
+        Some(value) => serde_json::from_str(value)
+            .map_err(|e| AppError::BadRequest(format!("Invalid {}: {}", name, e))),
+    }
+}
+
+pub fn to_data_url(mime_type: &str, bytes: &[u8]) -> String {
+    let mime_type = if mime_type.is_empty() {
+        "application/octet-stream"
+    } else {
+        mime_type
+    };
+    format!("data:{};base64,{}", mime_type, BASE64.encode(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imaging::encode::OutputFormat;
+
+    #[test]
+    fn test_upload_kind() {
+        assert_eq!(upload_kind(None).unwrap(), UploadKind::Json);
+        assert_eq!(upload_kind(Some("application/json; charset=utf-8")).unwrap(), UploadKind::Json);
+        assert_eq!(
+            upload_kind(Some("multipart/form-data; boundary=----abc")).unwrap(),
+            UploadKind::Multipart
+        );
+        assert_eq!(
+            upload_kind(Some("Image/PNG")).unwrap(),
+            UploadKind::Binary("image/png".to_string())
+        );
+        assert!(matches!(upload_kind(Some("text/plain")), Err(AppError::BadRequest(_))));
+    }
+
+    #[test]
+    fn test_max_body_bytes() {
+        let mb = 1024 * 1024;
+        assert_eq!(max_body_bytes(&UploadKind::Binary("image/png".to_string()), 3 * mb), 3 * mb);
+        assert_eq!(max_body_bytes(&UploadKind::Multipart, 3 * mb), 3 * mb + BODY_OVERHEAD_BYTES);
+        assert_eq!(max_body_bytes(&UploadKind::Json, 3 * mb), 4 * mb + BODY_OVERHEAD_BYTES);
+        assert!(matches!(image_too_large(1024), AppError::ImageTooLarge(msg) if msg == "Image is too large (max 1KB)"));
+    }
+
+    #[test]
+    fn test_read_bounded() {
+        use futures::executor::block_on;
+        use futures::stream;
+
+        let chunks = || stream::iter(vec![Ok(vec![1, 2]), Ok(vec![3, 4]), Ok(vec![5])]);
+        assert_eq!(block_on(read_bounded(chunks(), 5)).unwrap(), Some(vec![1, 2, 3, 4, 5]));
+        // Refused at the chunk that crosses the limit, without reading the rest
+        assert_eq!(block_on(read_bounded(chunks(), 4)).unwrap(), None);
+        assert_eq!(block_on(read_bounded(stream::iter(Vec::new()), 0)).unwrap(), Some(Vec::new()));
+    }
+
+    #[test]
+    fn test_image_upload_json() {
+        let request: TransformRequest =
+            serde_json::from_str(r#"{"image": "data:image/png;base64,aGk=", "emoji": "😊"}"#).unwrap();
+        assert_eq!(request.image, ImageUpload::DataUrl("data:image/png;base64,aGk=".to_string()));
+
+        // Uploaded bytes serialize as the data URL a JSON body would carry
+        let upload = ImageUpload::Bytes { mime_type: "image/png".to_string(), bytes: b"hi".to_vec() };
+        assert_eq!(serde_json::to_string(&upload).unwrap(), r#""data:image/png;base64,aGk=""#);
+        assert!(ImageUpload::Bytes { mime_type: "image/png".to_string(), bytes: Vec::new() }.is_empty());
+    }
+
+    #[test]
+    fn test_parse_option() {
+        assert_eq!(parse_option::<u8>("quality", None).unwrap(), None);
+        assert_eq!(parse_option::<u8>("quality", Some(" ".to_string())).unwrap(), None);
+        assert_eq!(parse_option::<u8>("quality", Some("80".to_string())).unwrap(), Some(80));
+        assert_eq!(
+            parse_option::<OutputFormat>("output_format", Some("jpg".to_string())).unwrap(),
+            Some(OutputFormat::Jpeg)
+        );
+        assert!(matches!(parse_option::<u32>("max_dimension", Some("big".to_string())), Err(AppError::BadRequest(_))));
+    }
+
+    #[test]
+    fn test_parse_json_list() {
+        use crate::models::SubjectEmoji;
+        use crate::prompt::subject::Subject;
+
+        assert!(parse_json_list::<SubjectEmoji>("subjects", None).unwrap().is_empty());
+        let subjects: Vec<SubjectEmoji> = parse_json_list(
+            "subjects",
+            Some(r#"[{"subject": 0, "emoji": "😊"}, {"subject": "0.5,0,0.5,1", "emoji": "😢"}]"#.to_string()),
+        )
+        .unwrap();
+        assert_eq!(subjects[0].subject, Subject::Index(0));
+        assert_eq!(subjects[1].emoji, "😢");
+        assert!(matches!(
+            parse_json_list::<SubjectEmoji>("subjects", Some("0=😊".to_string())),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_to_data_url() {
+        assert_eq!(to_data_url("image/png", b"hi"), "data:image/png;base64,aGk=");
+        assert_eq!(to_data_url("", b"hi"), "data:application/octet-stream;base64,aGk=");
+    }
+}
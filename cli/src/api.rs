@@ -1,40 +1,105 @@
+use reqwest::header::{HeaderMap, ACCEPT, CONTENT_TYPE};
 use reqwest::Client;
 use tracing::{info, error};
 
 use crate::error::{EmobananaError, Result};
-use crate::models::{TransformRequest, TransformResponse, ErrorResponse};
+use crate::models::{ErrorResponse, TransformMetadata, TransformRequest, TransformResponse, TransformedImage};
+use crate::utils::decode_base64_image;
+
+// Raw bytes when the server supports them, JSON from servers that predate binary responses
+const ACCEPT_IMAGE: &str = "image/*, application/json;q=0.5";
 
 pub struct ApiClient {
     client: Client,
     base_url: String,
+    api_key: Option<String>,
 }
 
 impl ApiClient {
-    pub fn new(base_url: String) -> Self {
+    pub fn new(base_url: String, api_key: Option<String>) -> Self {
         Self {
             client: Client::new(),
             base_url,
+            api_key,
         }
     }
 
-    pub async fn transform_image(&self, request: TransformRequest) -> Result<TransformResponse> {
+    pub async fn transform_image(&self, request: TransformRequest) -> Result<TransformedImage> {
         let url = format!("{}/transform", self.base_url);
         info!("Sending transformation request to {}", url);
 
-        let response = self.client
-            .post(&url)
-            .json(&request)
-            .send()
-            .await?;
+        let mut builder = self.client.post(&url).header(ACCEPT, ACCEPT_IMAGE).json(&request);
+        if let Some(api_key) = &self.api_key {
+            builder = builder.bearer_auth(api_key);
+        }
+
+        let response = builder.send().await?;
 
         if response.status().is_success() {
-            let transform_response: TransformResponse = response.json().await?;
-            info!("Transformation successful, request ID: {}", transform_response.metadata.request_id);
-            Ok(transform_response)
+            let is_image = response
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|ct| ct.to_str().ok())
+                .is_some_and(|ct| ct.starts_with("image/"));
+
+            let image = if is_image {
+                let metadata = metadata_from_headers(response.headers())?;
+                TransformedImage { bytes: response.bytes().await?.to_vec(), metadata }
+            } else {
+                let transform_response: TransformResponse = response.json().await?;
+                TransformedImage {
+                    bytes: decode_base64_image(&transform_response.transformed_image)?,
+                    metadata: transform_response.metadata,
+                }
+            };
+
+            info!("Transformation successful, request ID: {}", image.metadata.request_id);
+            Ok(image)
         } else {
             let error_response: ErrorResponse = response.json().await?;
             error!("API error: {}", error_response.error.message);
             Err(EmobananaError::Api(error_response.error.message))
         }
     }
-}
\ No newline at end of file
+}
+
+/// Reads the metadata a binary response carries in `X-*` headers.
+fn metadata_from_headers(headers: &HeaderMap) -> Result<TransformMetadata> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+            .ok_or_else(|| EmobananaError::Api(format!("Response is missing the {} header", name)))
+    };
+
+    Ok(TransformMetadata {
+        processing_time_ms: header("X-Processing-Time-Ms")?
+            .parse()
+            .map_err(|_| EmobananaError::Api("Invalid X-Processing-Time-Ms header".to_string()))?,
+        model_version: header("X-Model-Version")?,
+        request_id: header("X-Request-Id")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_metadata_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Request-Id", HeaderValue::from_static("req-123"));
+        headers.insert("X-Processing-Time-Ms", HeaderValue::from_static("1500"));
+        headers.insert("X-Model-Version", HeaderValue::from_static("1.0.0"));
+
+        let metadata = metadata_from_headers(&headers).unwrap();
+        assert_eq!(metadata.request_id, "req-123");
+        assert_eq!(metadata.processing_time_ms, 1500);
+        assert_eq!(metadata.model_version, "1.0.0");
+
+        headers.remove("X-Model-Version");
+        assert!(matches!(metadata_from_headers(&headers), Err(EmobananaError::Api(_))));
+    }
+}
@@ -1,6 +1,8 @@
 use clap::Parser;
 use config::{Config, ConfigError};
 
+use crate::models::SubjectEmoji;
+
 #[derive(Parser)]
 #[command(
     name = "emobanana-cli",
@@ -15,6 +17,9 @@ EXAMPLES:
     # Use custom output filename
     emobanana-cli --image dog.png --emoji 😢 --output sad_dog.png
 
+    # Save a side-by-side before/after with the emoji badge
+    emobanana-cli -i cat.jpg -e 😊 --composite side_by_side
+
     # Test against local development server
     emobanana-cli -i bird.jpg -e 😠 -u http://localhost:8787"
 )]
@@ -24,8 +29,17 @@ pub struct Args {
     pub image: String,
 
     /// Emoji to use for facial expression transformation
-    #[arg(short, long, help = "Emoji representing the desired facial expression (e.g., 😊, 😢, 😠)")]
-    pub emoji: String,
+    #[arg(
+        short,
+        long,
+        required_unless_present_any = ["description", "face"],
+        help = "Emoji representing the desired facial expression (e.g., 😊, 😢, 😠)"
+    )]
+    pub emoji: Option<String>,
+
+    /// Free-text expression
+    #[arg(short, long, help = "Expression in words (e.g., \"smug but tired\"), used alongside or instead of the emoji")]
+    pub description: Option<String>,
 
     /// Backend API URL
     #[arg(
@@ -44,6 +58,42 @@ pub struct Args {
         help = "Path where the transformed image will be saved"
     )]
     pub output: String,
+
+    /// API key sent as a Bearer token
+    #[arg(short = 'k', long, help = "API key to authenticate with (uses the key's quota instead of the per-IP limit)")]
+    pub api_key: Option<String>,
+
+    /// Expression intensity
+    #[arg(long, help = "How strongly to apply the expression: subtle, moderate, extreme, or 0.0-1.0")]
+    pub intensity: Option<String>,
+
+    /// Subject to edit in a photo with several faces
+    #[arg(
+        long,
+        help = "Which creature to edit when there are several: an index from the left starting at 0, or a box x,y,width,height as fractions of the image"
+    )]
+    pub subject: Option<String>,
+
+    /// Emoji for one creature of a group photo; repeat for each creature
+    #[arg(
+        long,
+        value_name = "SUBJECT=EMOJI",
+        conflicts_with_all = ["emoji", "description", "subject"],
+        help = "Give one creature of a group its own emoji, e.g. --face 0=😊 --face 1=😢; repeat for each creature"
+    )]
+    pub face: Vec<SubjectEmoji>,
+
+    /// Save a before/after composite instead of the transformed image alone
+    #[arg(
+        long,
+        value_name = "LAYOUT",
+        help = "Save the original and transformed images together with the emoji badge: side_by_side or diagonal"
+    )]
+    pub composite: Option<String>,
+
+    /// Padding around and between the images of a composite
+    #[arg(long, value_name = "PIXELS", requires = "composite", help = "Pixels of border around and between the composited images (default 16)")]
+    pub composite_padding: Option<u32>,
 }
 
 #[derive(Debug)]
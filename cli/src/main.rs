@@ -6,12 +6,11 @@ mod error;
 
 use clap::Parser;
 use tracing::info;
-use tracing_subscriber;
 
 use crate::cli::Args;
 use crate::api::ApiClient;
 use crate::models::TransformRequest;
-use crate::utils::{load_image_as_base64, save_base64_image};
+use crate::utils::{load_image_as_base64, output_format_for};
 use crate::error::Result;
 
 #[tokio::main]
@@ -24,21 +23,39 @@ async fn main() -> Result<()> {
 
     info!("Starting image transformation");
     info!("Image: {}", args.image);
-    info!("Emoji: {}", args.emoji);
+    if let Some(emoji) = &args.emoji {
+        info!("Emoji: {}", emoji);
+    }
+    if let Some(description) = &args.description {
+        info!("Description: {}", description);
+    }
+    for face in &args.face {
+        info!("Subject {}: {}", face.subject, face.emoji);
+    }
+    if let Some(layout) = &args.composite {
+        info!("Composite: {}", layout);
+    }
     info!("Backend URL: {}", args.url);
 
     let image_data = load_image_as_base64(&args.image)?;
 
     let request = TransformRequest {
         image: image_data,
-        emoji: args.emoji.clone(),
+        emoji: args.emoji.clone().unwrap_or_default(),
+        description: args.description.clone(),
+        output_format: output_format_for(&args.output),
+        intensity: args.intensity.clone(),
+        subject: args.subject.clone(),
+        subjects: args.face.clone(),
+        composite: args.composite.clone(),
+        composite_padding: args.composite_padding,
     };
 
-    let api_client = ApiClient::new(args.url.clone());
+    let api_client = ApiClient::new(args.url.clone(), args.api_key.clone());
 
     let response = api_client.transform_image(request).await?;
 
-    save_base64_image(&response.transformed_image, &args.output)?;
+    std::fs::write(&args.output, &response.bytes)?;
 
     info!("Transformation completed successfully!");
     info!("Request ID: {}", response.metadata.request_id);
@@ -1,17 +1,61 @@
+use std::str::FromStr;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize)]
 pub struct TransformRequest {
     pub image: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub emoji: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub intensity: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub subjects: Vec<SubjectEmoji>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub composite: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub composite_padding: Option<u32>,
+}
+
+/// An emoji for one subject of a group photo, written `SUBJECT=EMOJI` on the command line.
+#[derive(Debug, Clone, Serialize)]
+pub struct SubjectEmoji {
+    pub subject: String,
     pub emoji: String,
 }
 
+impl FromStr for SubjectEmoji {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((subject, emoji)) if !subject.trim().is_empty() && !emoji.trim().is_empty() => Ok(Self {
+                subject: subject.trim().to_string(),
+                emoji: emoji.trim().to_string(),
+            }),
+            _ => Err(format!("expected SUBJECT=EMOJI, got '{}'", s)),
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub struct TransformResponse {
     pub transformed_image: String,
     pub metadata: TransformMetadata,
 }
 
+/// A transformed image, whichever representation the server sent it in.
+pub struct TransformedImage {
+    pub bytes: Vec<u8>,
+    pub metadata: TransformMetadata,
+}
+
 #[derive(Deserialize)]
 pub struct TransformMetadata {
     pub processing_time_ms: u64,
@@ -45,11 +89,28 @@ mod tests {
         let request = TransformRequest {
             image: "data:image/png;base64,test".to_string(),
             emoji: "😊".to_string(),
+            description: None,
+            output_format: None,
+            intensity: None,
+            subject: None,
+            subjects: Vec::new(),
+            composite: None,
+            composite_padding: None,
         };
 
         let json = serde_json::to_string(&request).unwrap();
         assert!(json.contains("data:image/png;base64,test"));
         assert!(json.contains("😊"));
+        assert!(!json.contains("subjects"));
+        assert!(!json.contains("composite"));
+    }
+
+    #[test]
+    fn test_subject_emoji_from_str() {
+        let face: SubjectEmoji = "0.1,0.2,0.3,0.4=😢".parse().unwrap();
+        assert_eq!((face.subject.as_str(), face.emoji.as_str()), ("0.1,0.2,0.3,0.4", "😢"));
+        assert!("1".parse::<SubjectEmoji>().is_err());
+        assert!("=😊".parse::<SubjectEmoji>().is_err());
     }
 
     #[test]
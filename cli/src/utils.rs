@@ -10,14 +10,34 @@ pub fn load_image_as_base64(image_path: &str) -> Result<String> {
     }
 
     let image_data = fs::read(image_path)?;
+    let mime_type = detect_mime_type(&image_data);
     let base64_image = base64::engine::general_purpose::STANDARD.encode(image_data);
-    Ok(format!("data:image/png;base64,{}", base64_image))
+    Ok(format!("data:{};base64,{}", mime_type, base64_image))
 }
 
-pub fn save_base64_image(base64_data: &str, output_path: &str) -> Result<()> {
-    let image_data = decode_base64_image(base64_data)?;
-    fs::write(output_path, image_data)?;
-    Ok(())
+/// Labels the upload from its file signature, since the server rejects mismatched types.
+fn detect_mime_type(data: &[u8]) -> &'static str {
+    if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        "image/jpeg"
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        "image/png"
+    } else if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        "image/webp"
+    } else if data.len() >= 12 && &data[4..8] == b"ftyp" {
+        "image/heic"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+/// Server `output_format` matching the extension of `output_path`, if it names one.
+pub fn output_format_for(output_path: &str) -> Option<String> {
+    let extension = Path::new(output_path).extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "png" | "webp" => Some(extension),
+        "jpg" | "jpeg" => Some("jpeg".to_string()),
+        _ => None,
+    }
 }
 
 pub fn decode_base64_image(base64_data: &str) -> Result<Vec<u8>> {
@@ -38,8 +58,6 @@ pub fn decode_base64_image(base64_data: &str) -> Result<Vec<u8>> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
-    use tempfile::NamedTempFile;
 
     #[test]
     fn test_decode_base64_image_without_data_prefix() {
@@ -59,21 +77,26 @@ mod tests {
     }
 
     #[test]
-    fn test_load_image_as_base64_file_not_found() {
-        let result = load_image_as_base64("nonexistent_file.png");
-        assert!(matches!(result, Err(EmobananaError::FileNotFound(_))));
+    fn test_detect_mime_type() {
+        assert_eq!(detect_mime_type(&[0xFF, 0xD8, 0xFF, 0xE0]), "image/jpeg");
+        assert_eq!(detect_mime_type(b"\x89PNG\r\n\x1a\n\0\0"), "image/png");
+        assert_eq!(detect_mime_type(b"RIFF\0\0\0\0WEBPVP8L"), "image/webp");
+        assert_eq!(detect_mime_type(b"\0\0\0\x18ftypheic"), "image/heic");
+        assert_eq!(detect_mime_type(b"hello"), "application/octet-stream");
     }
 
     #[test]
-    fn test_save_and_load_base64_image() {
-        let test_data = b"Test image data";
-        let base64_string = base64::engine::general_purpose::STANDARD.encode(test_data);
-
-        let temp_file = NamedTempFile::new().unwrap();
-        let temp_path = temp_file.path().to_str().unwrap();
+    fn test_output_format_for() {
+        assert_eq!(output_format_for("out.png").as_deref(), Some("png"));
+        assert_eq!(output_format_for("out.JPG").as_deref(), Some("jpeg"));
+        assert_eq!(output_format_for("dir/out.webp").as_deref(), Some("webp"));
+        assert_eq!(output_format_for("out.gif"), None);
+        assert_eq!(output_format_for("out"), None);
+    }
 
-        save_base64_image(&base64_string, temp_path).unwrap();
-        let loaded_data = fs::read(temp_path).unwrap();
-        assert_eq!(loaded_data, test_data);
+    #[test]
+    fn test_load_image_as_base64_file_not_found() {
+        let result = load_image_as_base64("nonexistent_file.png");
+        assert!(matches!(result, Err(EmobananaError::FileNotFound(_))));
     }
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-import { useState, useCallback } from 'react';
+import { useState, useCallback, useEffect } from 'react';
 import EmojiGrid from './EmojiGrid';
 import ImageUpload from './ImageUpload';
 import TransformResult from './TransformResult';
@@ -17,6 +17,25 @@ interface TransformResponse {
   };
 }
 
+interface QuotaResponse {
+  limit: number | null;
+  remaining: number | null;
+  reset: number | null;
+}
+
+function readHeaderNumber(response: Response, name: string): number | null {
+  const value = response.headers.get(name);
+  return value === null ? null : Number(value);
+}
+
+// The quota may be rolling or per-minute, so the reset can be today or days away
+function formatReset(reset: number): string {
+  const resetsAt = new Date(reset * 1000);
+  return resetsAt.toDateString() === new Date().toDateString()
+    ? resetsAt.toLocaleTimeString([], { hour: 'numeric', minute: '2-digit' })
+    : resetsAt.toLocaleString([], { dateStyle: 'medium', timeStyle: 'short' });
+}
+
 interface ErrorResponse {
   error: {
     message: string;
@@ -40,6 +59,21 @@ export default function EmoBananaApp() {
     suggestion?: string;
   } | null>(null);
   const [requestsRemaining, setRequestsRemaining] = useState<number | null>(null);
+  const [quotaLimit, setQuotaLimit] = useState<number | null>(null);
+  const [quotaReset, setQuotaReset] = useState<number | null>(null);
+
+  useEffect(() => {
+    fetch(`${API_URL}/api/quota`)
+      .then((response) => (response.ok ? response.json() : null))
+      .then((quota: QuotaResponse | null) => {
+        if (quota) {
+          setRequestsRemaining(quota.remaining);
+          setQuotaLimit(quota.limit);
+          setQuotaReset(quota.reset);
+        }
+      })
+      .catch((err) => console.error('Quota error:', err));
+  }, []);
 
   const handleImageSelect = useCallback((imageData: string) => {
     setSelectedImage(imageData);
@@ -66,6 +100,15 @@ export default function EmoBananaApp() {
         }),
       });
 
+      const remaining = readHeaderNumber(response, 'X-RateLimit-Remaining');
+      if (remaining !== null) {
+        setRequestsRemaining(remaining);
+      }
+      const reset = readHeaderNumber(response, 'X-RateLimit-Reset');
+      if (reset !== null) {
+        setQuotaReset(reset);
+      }
+
       if (!response.ok) {
         const errorData: ErrorResponse = await response.json();
         const error = new Error(errorData.error?.message || `HTTP error! status: ${response.status}`);
@@ -88,10 +131,6 @@ export default function EmoBananaApp() {
           type: (err as any).type,
           suggestion: (err as any).suggestion,
         });
-
-        if (err.message.includes('Rate limit') || (err as any).code === 'rate_limit_exceeded') {
-          setRequestsRemaining(0);
-        }
       } else {
         setError({
           message: 'Failed to transform image',
@@ -108,7 +147,6 @@ export default function EmoBananaApp() {
     setTransformedImage(null);
     setProcessingTime(null);
     setError(null);
-    setRequestsRemaining(null);
   }, []);
 
   const handleRetry = useCallback(() => {
@@ -160,7 +198,10 @@ export default function EmoBananaApp() {
           {requestsRemaining === 0 && (
             <div className="mb-6 p-4 bg-yellow-50 dark:bg-yellow-950/30 border border-yellow-200 dark:border-yellow-800 rounded-xl max-w-2xl mx-auto">
               <p className="text-yellow-800 dark:text-yellow-200 text-center">
-                You've reached the daily limit of 5 transformations. Try again tomorrow!
+                {quotaLimit !== null
+                  ? `You've used all ${quotaLimit} transformations in your quota.`
+                  : "You've used all the transformations in your quota."}{' '}
+                {quotaReset !== null ? `It resets at ${formatReset(quotaReset)}.` : 'Try again later.'}
               </p>
             </div>
           )}
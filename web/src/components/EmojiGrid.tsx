@@ -7,9 +7,10 @@ const FACIAL_EMOJIS = [
   '🥳', '😏', '😒', '😞', '😔', '😟', '😕', '🙁', '☹️', '😣',
   '😖', '😫', '😩', '🥺', '😢', '😭', '😤', '😠', '😡', '🤬',
   '🤯', '😳', '🥵', '🥶', '😱', '😨', '😰', '😥', '😓', '🤗',
-  '🤔', '🤭', '🤫', '🤥', '😶', '😐', '😑', '😬', '🙄', '😯',
-  '😦', '😧', '😮', '😲', '🥱', '😴', '🤤', '😪', '😵', '🤐',
-  '🥴', '🤢', '🤮', '🤧', '😷', '🤒', '🤕', '🤑', '🤠'
+  '🤔', '🤭', '🤫', '🤥', '😶', '😶‍🌫️', '😐', '😑', '😬', '🙄',
+  '😯', '😦', '😧', '😮', '😮‍💨', '😲', '🥱', '😴', '🤤', '😪',
+  '😵', '😵‍💫', '🤐', '🥴', '🤢', '🤮', '🤧', '😷', '🤒', '🤕',
+  '🤑', '🤠'
 ];
 
 interface EmojiGridProps {
@@ -56,13 +57,13 @@ export default function EmojiGrid({ onEmojiSelect, selectedEmoji }: EmojiGridPro
       '🥵': 'Hot', '🥶': 'Cold', '😱': 'Scream', '😨': 'Fearful',
       '😰': 'Anxious', '😥': 'Sad Sweat', '😓': 'Sweat', '🤗': 'Hug',
       '🤔': 'Thinking', '🤭': 'Hand Mouth', '🤫': 'Shush', '🤥': 'Lying',
-      '😶': 'No Mouth', '😐': 'Neutral', '😑': 'Expressionless', '😬': 'Grimace',
-      '🙄': 'Eye Roll', '😯': 'Hushed', '😦': 'Frowning', '😧': 'Anguished',
-      '😮': 'Open Mouth', '😲': 'Astonished', '🥱': 'Yawn', '😴': 'Sleep',
-      '🤤': 'Drool', '😪': 'Sleepy', '😵': 'Dizzy', '🤐': 'Zipper',
-      '🥴': 'Woozy', '🤢': 'Nauseated', '🤮': 'Vomit', '🤧': 'Sneeze',
-      '😷': 'Mask', '🤒': 'Thermometer', '🤕': 'Bandage', '🤑': 'Money',
-      '🤠': 'Cowboy'
+      '😶': 'No Mouth', '😶‍🌫️': 'In Clouds', '😐': 'Neutral', '😑': 'Expressionless',
+      '😬': 'Grimace', '🙄': 'Eye Roll', '😯': 'Hushed', '😦': 'Frowning',
+      '😧': 'Anguished', '😮': 'Open Mouth', '😮‍💨': 'Exhale', '😲': 'Astonished',
+      '🥱': 'Yawn', '😴': 'Sleep', '🤤': 'Drool', '😪': 'Sleepy',
+      '😵': 'Dizzy', '😵‍💫': 'Spiral Eyes', '🤐': 'Zipper', '🥴': 'Woozy',
+      '🤢': 'Nauseated', '🤮': 'Vomit', '🤧': 'Sneeze', '😷': 'Mask',
+      '🤒': 'Thermometer', '🤕': 'Bandage', '🤑': 'Money', '🤠': 'Cowboy'
     };
     return names[emoji] || 'Emoji';
   };
@@ -110,7 +110,7 @@ export default function ImageUpload({ onImageSelect, disabled = false }: ImageUp
               </p>
             </div>
             <div className="text-xs text-slate-400 dark:text-slate-500">
-              Supports JPG, PNG, GIF, WebP (max 10MB)
+              Supports JPG, PNG, WebP, HEIC (max 10MB)
             </div>
           </div>
         </div>
@@ -17,9 +17,11 @@ export default function TransformResult({
   const [sliderPosition, setSliderPosition] = useState(50);
 
   const handleDownload = () => {
+    const mimeType = transformedImage.slice(5, transformedImage.indexOf(';'));
+    const extension = mimeType === 'image/jpeg' ? 'jpg' : mimeType.replace('image/', '');
     const link = document.createElement('a');
-    link.href = `data:image/jpeg;base64,${transformedImage}`;
-    link.download = `emobanana-${Date.now()}.jpg`;
+    link.href = transformedImage;
+    link.download = `emobanana-${Date.now()}.${extension}`;
     link.click();
   };
 
@@ -62,7 +64,7 @@ export default function TransformResult({
             <div className="space-y-2">
               <p className="text-center text-sm font-medium text-slate-600 dark:text-slate-400">Transformed</p>
               <img
-                src={`data:image/jpeg;base64,${transformedImage}`}
+                src={transformedImage}
                 alt="Transformed"
                 className="w-full h-auto rounded-xl shadow-lg"
               />
@@ -80,7 +82,7 @@ export default function TransformResult({
               style={{ clipPath: `inset(0 ${100 - sliderPosition}% 0 0)` }}
             >
               <img
-                src={`data:image/jpeg;base64,${transformedImage}`}
+                src={transformedImage}
                 alt="Transformed"
                 className="w-full h-auto"
               />